@@ -1,40 +1,367 @@
+use crate::encoding::{self, Encoding, EncodingError};
+use crate::rope::LineRope;
+use crate::storage::TextStorage;
+use crate::types::{Cursor, LineEnding};
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Buffer {
-    pub(crate) lines: Vec<String>,
+    lines: LineRope,
+    line_ending: LineEnding,
+    // The break after each line, the last entry being the final line break,
+    // while the source's styles are still mixed. `None` once every break
+    // is `line_ending`.
+    line_endings: Option<Vec<LineEnding>>,
+    bom: bool,
+    final_newline: bool,
+    ensure_final_newline: bool,
+    encoding: Encoding,
 }
 
+const BOM: char = '\u{feff}';
+
 impl Buffer {
     pub fn new() -> Self {
         Self {
-            lines: vec![String::new()],
+            lines: LineRope::from_lines(vec![String::new()]),
+            line_ending: LineEnding::default(),
+            line_endings: None,
+            bom: false,
+            final_newline: false,
+            ensure_final_newline: false,
+            encoding: Encoding::default(),
         }
     }
 
+    // Decodes raw file bytes. With no `encoding` one is picked by
+    // `Encoding::detect`. The encoding is remembered for `encoding()`.
+    pub fn from_bytes(bytes: &[u8], encoding: Option<Encoding>) -> Result<Self, EncodingError> {
+        let encoding = encoding.unwrap_or_else(|| Encoding::detect(bytes));
+        let text = encoding::decode(bytes, encoding)?;
+        let mut buffer = Self::from_text(&text);
+        buffer.encoding = encoding;
+        Ok(buffer)
+    }
+
+    // Splits on "\r\n", '\n', and lone '\r'. The most common style becomes
+    // the buffer's line ending (LF on ties or when there are no breaks); when
+    // styles are mixed, each break's own style is kept too. A leading BOM and
+    // a final line break are remembered rather than kept as text.
     pub fn from_text(text: &str) -> Self {
-        let mut lines: Vec<String> = text.split('\n').map(|line| line.to_string()).collect();
-        if lines.is_empty() {
-            lines.push(String::new());
+        let bom = text.starts_with(BOM);
+        let text = text.strip_prefix(BOM).unwrap_or(text);
+        let mut lines = Vec::new();
+        let mut endings = Vec::new();
+        let mut counts = [0usize; 3];
+        let bytes = text.as_bytes();
+        let mut start = 0;
+        let mut idx = 0;
+        while idx < bytes.len() {
+            let (ending, len) = match bytes[idx] {
+                b'\n' => (LineEnding::Lf, 1),
+                b'\r' if bytes.get(idx + 1) == Some(&b'\n') => (LineEnding::CrLf, 2),
+                b'\r' => (LineEnding::Cr, 1),
+                _ => {
+                    idx += 1;
+                    continue;
+                }
+            };
+            lines.push(text[start..idx].to_string());
+            endings.push(ending);
+            counts[ending as usize] += 1;
+            idx += len;
+            start = idx;
+        }
+        let final_newline = start > 0 && start == text.len();
+        if !final_newline {
+            lines.push(text[start..].to_string());
+        }
+
+        let line_ending = [LineEnding::Lf, LineEnding::CrLf, LineEnding::Cr]
+            .into_iter()
+            .rev()
+            .max_by_key(|&ending| counts[ending as usize])
+            .unwrap_or_default();
+        let mixed = counts.iter().filter(|&&count| count > 0).count() > 1;
+        if !final_newline {
+            endings.push(line_ending);
+        }
+        Self {
+            lines: LineRope::from_lines(lines),
+            line_ending,
+            line_endings: mixed.then_some(endings),
+            bom,
+            final_newline,
+            ensure_final_newline: false,
+            encoding: Encoding::default(),
         }
-        Self { lines }
     }
 
+    // The text as it should be saved: the BOM if any, every line break in
+    // its own style (the buffer's line ending unless styles are mixed), and
+    // the final line break if any.
     pub fn as_text(&self) -> String {
-        self.lines.join("\n")
+        let mut text = String::with_capacity(self.char_count() + 2 * self.lines.len() + 3);
+        if self.bom {
+            text.push(BOM);
+        }
+        for (row, line) in self.lines.iter().enumerate() {
+            if row > 0 {
+                text.push_str(self.ending_after(row - 1).as_str());
+            }
+            text.push_str(line);
+        }
+        if self.writes_final_newline() {
+            text.push_str(self.ending_after(self.lines.len() - 1).as_str());
+        }
+        text
+    }
+
+    // The style of the break after `row`; for the last row, of the final
+    // line break.
+    fn ending_after(&self, row: usize) -> LineEnding {
+        self.line_endings
+            .as_ref()
+            .and_then(|endings| endings.get(row).copied())
+            .unwrap_or(self.line_ending)
+    }
+
+    // Encodes `as_text` for saving. Fails with every char the encoding cannot
+    // represent; the BOM is dropped for encodings that do not define one.
+    pub fn to_bytes(&self, encoding: Encoding) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = Vec::with_capacity(self.char_count() + self.lines.len());
+        let mut unrepresentable = Vec::new();
+        let separators = [LineEnding::Lf, LineEnding::CrLf, LineEnding::Cr].map(|ending| {
+            let mut separator = Vec::new();
+            for ch in ending.as_str().chars() {
+                encoding::encode_char(ch, encoding, &mut separator);
+            }
+            separator
+        });
+
+        if self.bom && encoding.defines_bom() {
+            encoding::encode_char(BOM, encoding, &mut bytes);
+        }
+        for (row, line) in self.lines.iter().enumerate() {
+            if row > 0 {
+                bytes.extend_from_slice(&separators[self.ending_after(row - 1) as usize]);
+            }
+            for (col, ch) in line.chars().enumerate() {
+                if !encoding::encode_char(ch, encoding, &mut bytes) {
+                    unrepresentable.push((Cursor::new(row, col), ch));
+                }
+            }
+        }
+        if self.writes_final_newline() {
+            let last = self.ending_after(self.lines.len() - 1);
+            bytes.extend_from_slice(&separators[last as usize]);
+        }
+
+        if unrepresentable.is_empty() {
+            Ok(bytes)
+        } else {
+            Err(EncodingError::Unrepresentable(unrepresentable))
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    // The encoding a host should save with; `to_bytes` takes it explicitly.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    pub fn set_bom(&mut self, bom: bool) {
+        self.bom = bom;
+    }
+
+    // Whether the source text ended with a line break. The break is not an
+    // extra empty line.
+    pub fn has_final_newline(&self) -> bool {
+        self.final_newline
+    }
+
+    pub fn set_final_newline(&mut self, final_newline: bool) {
+        self.final_newline = final_newline;
+    }
+
+    pub fn ensures_final_newline(&self) -> bool {
+        self.ensure_final_newline
+    }
+
+    // When set, `as_text` ends every non-empty buffer with a line break.
+    pub fn set_ensure_final_newline(&mut self, ensure: bool) {
+        self.ensure_final_newline = ensure;
+    }
+
+    fn writes_final_newline(&self) -> bool {
+        self.final_newline || (self.ensure_final_newline && self.char_count() > 0)
+    }
+
+    // Everything besides the lines that affects what gets saved.
+    pub(crate) fn format_key(&self) -> (Encoding, LineEnding, bool, bool, bool) {
+        (
+            self.encoding,
+            self.line_ending,
+            self.line_endings.is_some(),
+            self.bom,
+            self.writes_final_newline(),
+        )
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    // Whether the source text used more than one line-ending style and has
+    // not been converted since. Such a buffer is written back with each
+    // break in its original style; new breaks use `line_ending`.
+    pub fn has_mixed_line_endings(&self) -> bool {
+        self.line_endings.is_some()
+    }
+
+    // Converts every line break to `line_ending` for `as_text`.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+        self.line_endings = None;
     }
 
     pub fn line(&self, row: usize) -> Option<&str> {
-        self.lines.get(row).map(|line| line.as_str())
+        self.lines.get(row)
     }
 
     pub fn line_count(&self) -> usize {
         self.lines.len()
     }
 
-    pub(crate) fn line_len_chars(&self, row: usize) -> usize {
-        match self.lines.get(row) {
-            Some(line) => line.chars().count(),
-            None => 0,
+    // Total chars, counting one per line break.
+    pub fn char_count(&self) -> usize {
+        self.lines.total_chars() + self.lines.len().saturating_sub(1)
+    }
+
+    // Absolute char offset of a position, counting one char per line break.
+    pub fn char_offset(&self, position: Cursor) -> usize {
+        self.lines.char_offset(position.row, position.col)
+    }
+
+    pub fn position_at(&self, char_offset: usize) -> Cursor {
+        let (row, col) = self.lines.position_at(char_offset);
+        Cursor::new(row, col)
+    }
+}
+
+impl TextStorage for Buffer {
+    fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn line(&self, row: usize) -> Option<&str> {
+        self.lines.get(row)
+    }
+
+    fn insert(&mut self, at: Cursor, text: &str) -> Cursor {
+        if at.row >= self.lines.len() {
+            return at;
+        }
+
+        let mut pieces = text.split('\n');
+        let first = pieces.next().unwrap_or_default();
+        let rest: Vec<&str> = pieces.collect();
+        let Some((last, middle)) = rest.split_last() else {
+            self.lines.insert(at.row, at.col, first);
+            return Cursor::new(at.row, at.col + first.chars().count());
+        };
+
+        // The part of the line after `at` ends up after the last piece.
+        self.lines.split_line(at.row, at.col);
+        self.lines.insert(at.row, at.col, first);
+        let middle = middle.iter().map(|piece| piece.to_string()).collect();
+        self.lines.insert_lines(at.row + 1, middle);
+        let last_row = at.row + rest.len();
+        self.lines.insert(last_row, 0, last);
+        // The break after `at.row` now follows the last inserted line.
+        if let Some(endings) = &mut self.line_endings {
+            let added = std::iter::repeat_n(self.line_ending, rest.len());
+            endings.splice(at.row..at.row, added);
+        }
+
+        Cursor::new(last_row, last.chars().count())
+    }
+
+    fn remove(&mut self, start: Cursor, end: Cursor) -> String {
+        if start.row >= self.lines.len() || end.row >= self.lines.len() {
+            return String::new();
+        }
+
+        if start.row == end.row {
+            return self.lines.remove(start.row, start.col, end.col);
+        }
+
+        let end_part = self.lines.remove(end.row, 0, end.col);
+        let middle = self.lines.remove_lines(start.row + 1, end.row);
+        let mut removed = self.lines.remove(start.row, start.col, usize::MAX);
+        self.lines.join_lines(start.row);
+        if let Some(endings) = &mut self.line_endings {
+            endings.drain(start.row..end.row);
+        }
+
+        for line in middle {
+            removed.push('\n');
+            removed.push_str(&line);
+        }
+        removed.push('\n');
+        removed.push_str(&end_part);
+        removed
+    }
+
+    fn line_len_chars(&self, row: usize) -> usize {
+        self.lines.line_chars(row)
+    }
+
+    fn char_to_byte(&self, row: usize, col: usize) -> usize {
+        self.lines.char_to_byte(row, col)
+    }
+
+    fn byte_to_char(&self, row: usize, byte: usize) -> usize {
+        self.lines.byte_to_char(row, byte)
+    }
+
+    fn plain_prefix_chars(&self, row: usize) -> usize {
+        self.lines.plain_prefix(row)
+    }
+
+    fn untabbed_width(&self, row: usize, start: usize, end: usize) -> Option<usize> {
+        self.lines.untabbed_width(row, start, end)
+    }
+
+    // Copies only the needed chars of the first and last lines, so a short
+    // range inside a long line stays cheap, and walks the rope once for the
+    // lines between.
+    fn text_range(&self, start: Cursor, end: Cursor) -> String {
+        if start.row >= self.lines.len() || end.row >= self.lines.len() || start >= end {
+            return String::new();
+        }
+        if start.row == end.row {
+            return self.lines.slice(start.row, start.col, end.col);
+        }
+
+        let mut text = self.lines.slice(start.row, start.col, usize::MAX);
+        for line in self
+            .lines
+            .iter_from(start.row + 1)
+            .take(end.row - start.row - 1)
+        {
+            text.push('\n');
+            text.push_str(line);
         }
+        text.push('\n');
+        text.push_str(&self.lines.slice(end.row, 0, end.col));
+        text
     }
 }
 
@@ -1,150 +1,1772 @@
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
 use crate::buffer::Buffer;
-use crate::text::{char_to_byte_index, slice_line};
-use crate::types::{Action, Cursor, Viewport};
+use crate::encoding::Encoding;
+use crate::history::{Edit, EditKind, History, HistoryError, text_end};
+use crate::registers::{
+    Clipboard, KillRing, RegisterContent, RegisterKind, Registers, UNNAMED_REGISTER,
+};
+use crate::storage::{TextStorage, find_next};
+use crate::text::{
+    cell_width, char_to_byte_index, char_width, display_col_from, display_end,
+    has_cluster_start_before, is_word_char, next_grapheme_boundary, normalize_line_endings,
+    prev_grapheme_boundary, slice_line, visible_char_range, wrap_indent, wrap_line,
+};
+use crate::types::{Action, BlockSelection, Cursor, LineEnding, Selection, Viewport, VisualRow};
+
+// Chars read on each side of a column when looking for the grapheme cluster
+// around it; doubled while that is not enough.
+const CLUSTER_CONTEXT: usize = 32;
+
+// Where the last `Yank`/`YankPop` put its text, so `YankPop` can replace it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct YankState {
+    start: Cursor,
+    end: Cursor,
+    index: usize,
+}
+
+// The column a head is aiming for during a run of vertical moves: a display
+// column of the line for `MoveUp`/`MoveDown`, or a screen column of the
+// visual row for `MoveVisualUp`/`MoveVisualDown`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct GoalCol {
+    head: Cursor,
+    visual: bool,
+    col: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Editor<S: TextStorage = Buffer> {
+    buffer: S,
+    cursor: Cursor,
+    anchor: Option<Cursor>,
+    // Every selection other than the primary one held in `cursor`/`anchor`.
+    others: Vec<Selection>,
+    block: Option<BlockSelection>,
+    viewport: Viewport,
+    dirty: bool,
+    history: History,
+    registers: Registers,
+    pending_register: Option<char>,
+    kill_ring: KillRing,
+    // Whether the previous action was a kill, so the next kill extends it.
+    kill_chain: bool,
+    last_yank: Option<YankState>,
+    // Whether horizontal motions and single-char deletes step over whole
+    // grapheme clusters rather than chars.
+    grapheme_clusters: bool,
+    // Display columns between tab stops.
+    tab_width: usize,
+    soft_wrap: bool,
+    wrap_at_words: bool,
+    // Display columns continuation rows are indented by under soft wrap.
+    wrap_indent: usize,
+    // Kept only while vertical motions follow each other.
+    goal_cols: Vec<GoalCol>,
+}
+
+// Hosts may hand an editor to another thread, so it has to stay `Send`.
+fn _assert_send<T: Send>() {}
+
+fn _assert_editor_is_send() {
+    _assert_send::<Editor>();
+}
+
+impl<S: TextStorage> Editor<S> {
+    pub fn new(buffer: S, viewport: Viewport) -> Self {
+        let mut editor = Self {
+            buffer,
+            cursor: Cursor::new(0, 0),
+            anchor: None,
+            others: Vec::new(),
+            block: None,
+            viewport,
+            dirty: false,
+            history: History::default(),
+            registers: Registers::default(),
+            pending_register: None,
+            kill_ring: KillRing::default(),
+            kill_chain: false,
+            last_yank: None,
+            grapheme_clusters: true,
+            tab_width: 4,
+            soft_wrap: false,
+            wrap_at_words: false,
+            wrap_indent: 0,
+            goal_cols: Vec::new(),
+        };
+
+        editor.clamp_cursor();
+        editor.ensure_cursor_visible();
+        editor
+    }
+
+    pub fn buffer(&self) -> &S {
+        &self.buffer
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    pub fn selection(&self) -> Selection {
+        Selection::new(self.anchor.unwrap_or(self.cursor), self.cursor)
+    }
+
+    pub fn set_selection(&mut self, selection: Selection) {
+        self.load_selection(selection);
+        self.clamp_cursor();
+        self.ensure_cursor_visible();
+    }
+
+    pub fn selections(&self) -> Vec<Selection> {
+        let mut selections = self.others.clone();
+        selections.push(self.selection());
+        selections.sort_by_key(Selection::start);
+        selections
+    }
+
+    pub fn add_selection(&mut self, selection: Selection) {
+        let primary = self.selection();
+        self.others.push(primary);
+        self.set_selection(selection);
+        self.merge_selections();
+    }
+
+    pub fn add_cursor(&mut self, cursor: Cursor) {
+        self.add_selection(Selection::caret(cursor));
+    }
+
+    pub fn clear_secondary_cursors(&mut self) {
+        self.others.clear();
+    }
+
+    pub fn block_selection(&self) -> Option<BlockSelection> {
+        self.block
+    }
+
+    pub fn set_block_selection(&mut self, block: BlockSelection) {
+        let last_row = self.buffer.line_count().saturating_sub(1);
+        let clamp_row = |position: Cursor| Cursor::new(position.row.min(last_row), position.col);
+        let block = BlockSelection::new(clamp_row(block.anchor), clamp_row(block.head));
+        self.anchor = None;
+        self.others.clear();
+        self.block = Some(block);
+        self.cursor = block.head;
+        self.clamp_cursor();
+        self.ensure_cursor_visible();
+    }
+
+    pub fn clear_block_selection(&mut self) {
+        self.block = None;
+    }
+
+    // Text of each row inside the block, without padding for short lines.
+    pub fn block_text(&self) -> Option<Vec<String>> {
+        let block = self.block?;
+        let rows = (block.top_row()..=block.bottom_row()).map(|row| {
+            let line_len = self.buffer.line_len_chars(row);
+            let left = block.left_col().min(line_len);
+            let right = block.right_col().min(line_len);
+            self.buffer
+                .text_range(Cursor::new(row, left), Cursor::new(row, right))
+        });
+        Some(rows.collect())
+    }
+
+    // Inserts `rows` as a rectangle whose top-left corner is the cursor,
+    // padding short lines and appending lines at the end of the buffer as
+    // needed. The whole paste is one undo step.
+    pub fn paste_block<R: AsRef<str>>(&mut self, rows: &[R]) {
+        if rows.is_empty() || self.buffer.is_read_only() {
+            return;
+        }
+
+        self.clamp_cursor();
+        let before = self.history_cursors();
+        self.block = None;
+        self.anchor = None;
+        self.others.clear();
+
+        // A line break inside a row starts the next block row.
+        let rows: Vec<String> = rows
+            .iter()
+            .flat_map(|row| {
+                normalize_line_endings(row.as_ref())
+                    .split('\n')
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        self.insert_block(&rows);
+        self.dirty = true;
+        self.history.commit(before, self.history_cursors(), None);
+        self.clamp_cursor();
+        self.ensure_cursor_visible();
+    }
+
+    pub fn selected_text(&self) -> String {
+        let selection = self.selection();
+        self.buffer.text_range(selection.start(), selection.end())
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = viewport;
+        self.ensure_cursor_visible();
+    }
+
+    pub fn grapheme_clusters(&self) -> bool {
+        self.grapheme_clusters
+    }
+
+    pub fn set_grapheme_clusters(&mut self, enabled: bool) {
+        self.grapheme_clusters = enabled;
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    // Tabs render up to the next multiple of `tab_width` (at least 1).
+    // Changing it rescrolls so the cursor stays visible.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+        self.ensure_cursor_visible();
+    }
+
+    pub fn soft_wrap(&self) -> bool {
+        self.soft_wrap
+    }
+
+    // With soft wrap, lines longer than the viewport continue on the next
+    // screen rows instead of scrolling horizontally.
+    pub fn set_soft_wrap(&mut self, enabled: bool) {
+        self.soft_wrap = enabled;
+        self.ensure_cursor_visible();
+    }
+
+    pub fn wrap_at_words(&self) -> bool {
+        self.wrap_at_words
+    }
+
+    // Soft-wrapped rows end after whitespace rather than inside a word when
+    // the row has any.
+    pub fn set_wrap_at_words(&mut self, enabled: bool) {
+        self.wrap_at_words = enabled;
+        self.ensure_cursor_visible();
+    }
+
+    pub fn wrap_indent(&self) -> usize {
+        self.wrap_indent
+    }
+
+    pub fn set_wrap_indent(&mut self, indent: usize) {
+        self.wrap_indent = indent;
+        self.ensure_cursor_visible();
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn reset_dirty(&mut self) {
+        self.dirty = false;
+    }
+
+    pub fn register(&self, name: char) -> Option<RegisterContent> {
+        self.registers.get(name)
+    }
+
+    // Host text may use "\r\n" or '\r' between lines; registers hold '\n'.
+    pub fn set_register(&mut self, name: char, mut content: RegisterContent) -> bool {
+        if !Registers::is_valid(name) {
+            return false;
+        }
+        content.text = normalize_line_endings(&content.text).into_owned();
+        self.registers.set(name, content);
+        true
+    }
+
+    pub fn set_clipboard(&mut self, clipboard: impl Clipboard + Send + 'static) {
+        self.registers
+            .set_clipboard(Some(Arc::new(Mutex::new(clipboard))));
+    }
+
+    pub fn remove_clipboard(&mut self) {
+        self.registers.set_clipboard(None);
+    }
+
+    pub fn kill_ring(&self) -> &[String] {
+        self.kill_ring.entries()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    pub fn save_history(&self) -> Vec<u8> {
+        self.history
+            .encode(&self.buffer.text(), &self.history_cursors())
+    }
+
+    pub fn load_history(&mut self, data: &[u8]) -> Result<(), HistoryError> {
+        if self.buffer.is_read_only() {
+            return Err(HistoryError::ReadOnly);
+        }
+        self.history = History::decode(data, &self.buffer.text())?;
+        Ok(())
+    }
+
+    pub fn begin_transaction(&mut self) {
+        self.clamp_cursor();
+        self.history.begin_transaction(self.history_cursors());
+    }
+
+    pub fn end_transaction(&mut self) {
+        self.history.end_transaction(self.history_cursors());
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.history.in_transaction()
+    }
+
+    pub fn undo(&mut self) -> bool {
+        if self.buffer.is_read_only() {
+            return false;
+        }
+        self.history.finish_transaction(self.history_cursors());
+        let cursors = self.history.undo(&mut self.buffer);
+        self.restore_history_cursors(cursors)
+    }
+
+    pub fn redo(&mut self) -> bool {
+        if self.buffer.is_read_only() {
+            return false;
+        }
+        self.history.finish_transaction(self.history_cursors());
+        let cursors = self.history.redo(&mut self.buffer);
+        self.restore_history_cursors(cursors)
+    }
+
+    pub fn current_state(&self) -> usize {
+        self.history.current_state()
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.history.state_count()
+    }
+
+    pub fn state_time(&self, state: usize) -> Option<SystemTime> {
+        self.history.state_time(state)
+    }
+
+    pub fn redo_branch_count(&self) -> usize {
+        self.history.redo_branch_count()
+    }
+
+    pub fn select_redo_branch(&mut self, index: usize) -> bool {
+        self.history.select_redo_branch(index)
+    }
+
+    pub fn goto_state(&mut self, state: usize) -> bool {
+        if self.buffer.is_read_only() {
+            return false;
+        }
+        self.history.finish_transaction(self.history_cursors());
+        let cursors = self.history.goto(state, &mut self.buffer);
+        self.restore_history_cursors(cursors)
+    }
+
+    pub fn earlier(&mut self, count: usize) -> bool {
+        let state = self.history.current_state().saturating_sub(count);
+        self.goto_state(state)
+    }
+
+    pub fn later(&mut self, count: usize) -> bool {
+        let last = self.history.state_count() - 1;
+        let state = self.history.current_state().saturating_add(count).min(last);
+        self.goto_state(state)
+    }
+
+    pub fn goto_time(&mut self, time: SystemTime) -> bool {
+        let state = self.history.state_at(time);
+        self.goto_state(state)
+    }
+
+    pub fn apply(&mut self, action: Action) {
+        self.clamp_cursor();
+        if self.buffer.is_read_only() && Self::edits_text(action) {
+            self.pending_register = None;
+            return;
+        }
+
+        let before = self.history_cursors();
+        let register = match action {
+            Action::SelectRegister(_) => None,
+            _ => self.pending_register.take(),
+        };
+        if self.block.is_some() && !Self::keeps_block(action) {
+            self.block = None;
+        }
+
+        let (mutated, kind) = match action {
+            Action::SelectRegister(name) => {
+                self.pending_register = Registers::is_valid(name).then_some(name);
+                (false, None)
+            }
+            Action::Copy => {
+                let content = self.copy_content();
+                self.registers.record(register, content, false);
+                self.block = None;
+                (false, None)
+            }
+            Action::Cut => (self.cut(register), None),
+            Action::KillWordForward | Action::KillWordBackward | Action::KillToLineEnd => {
+                (self.kill(action), None)
+            }
+            Action::Yank => (self.yank(), None),
+            Action::YankPop => (self.yank_pop(), None),
+            Action::Paste | Action::PasteBefore => {
+                let name = register.unwrap_or(UNNAMED_REGISTER);
+                match self.registers.get(name) {
+                    Some(content) => {
+                        let before_cursor = action == Action::PasteBefore;
+                        self.for_each_cursor(|editor| (editor.paste(&content, before_cursor), None))
+                    }
+                    None => (false, None),
+                }
+            }
+            Action::BlockSelectLeft
+            | Action::BlockSelectRight
+            | Action::BlockSelectUp
+            | Action::BlockSelectDown => {
+                self.extend_block(action);
+                (false, None)
+            }
+            Action::Insert(ch) if !matches!(ch, '\n' | '\r') && self.block.is_some() => {
+                (self.block_insert(ch), Some(EditKind::Insert))
+            }
+            Action::DeleteBackward if self.block.is_some() => {
+                (self.block_delete(false), Some(EditKind::DeleteBackward))
+            }
+            Action::DeleteForward if self.block.is_some() => {
+                (self.block_delete(true), Some(EditKind::DeleteForward))
+            }
+            Action::SelectAll => {
+                self.others.clear();
+                self.select_all();
+                (false, None)
+            }
+            Action::AddCursorAbove => {
+                self.add_cursor_vertical(false);
+                (false, None)
+            }
+            Action::AddCursorBelow => {
+                self.add_cursor_vertical(true);
+                (false, None)
+            }
+            Action::AddNextOccurrence => {
+                self.add_next_occurrence();
+                (false, None)
+            }
+            Action::RemoveSecondaryCursors => {
+                self.others.clear();
+                (false, None)
+            }
+            Action::Undo => {
+                self.undo();
+                (false, None)
+            }
+            Action::Redo => {
+                self.redo();
+                (false, None)
+            }
+            Action::Earlier => {
+                self.earlier(1);
+                (false, None)
+            }
+            Action::Later => {
+                self.later(1);
+                (false, None)
+            }
+            Action::PageUp | Action::PageDown | Action::HalfPageUp | Action::HalfPageDown => {
+                self.scroll_page(action);
+                (false, None)
+            }
+            _ => self.for_each_cursor(|editor| editor.apply_at_cursor(action)),
+        };
+
+        self.record_edit(before, mutated, kind);
+
+        if !matches!(
+            action,
+            Action::KillWordForward | Action::KillWordBackward | Action::KillToLineEnd
+        ) {
+            self.kill_chain = false;
+        }
+        if !matches!(action, Action::Yank | Action::YankPop) {
+            self.last_yank = None;
+        }
+        if !Self::is_vertical_motion(action) {
+            self.goal_cols.clear();
+        }
+
+        self.settle_selections();
+    }
+
+    // Commits an edit made since `before` as one undo step, coalescing runs
+    // of the same `kind`; anything else ends the current run.
+    fn record_edit(&mut self, before: Vec<Cursor>, mutated: bool, kind: Option<EditKind>) {
+        if mutated {
+            self.dirty = true;
+            self.history.commit(before, self.history_cursors(), kind);
+        } else {
+            self.history.break_run();
+        }
+    }
+
+    fn settle_selections(&mut self) {
+        self.merge_selections();
+        self.clamp_cursor();
+        self.ensure_cursor_visible();
+    }
+
+    // Runs `op` once per selection in document order. Edits made at one
+    // selection shift the others through `insert_text`/`remove_text`.
+    fn for_each_cursor(
+        &mut self,
+        mut op: impl FnMut(&mut Self) -> (bool, Option<EditKind>),
+    ) -> (bool, Option<EditKind>) {
+        if self.others.is_empty() {
+            return op(self);
+        }
+
+        let primary = self.selection();
+        let mut selections = std::mem::take(&mut self.others);
+        selections.push(primary);
+        selections.sort_by_key(Selection::start);
+        let primary_index = selections
+            .iter()
+            .position(|selection| *selection == primary)
+            .unwrap_or_default();
+        self.others = selections;
+
+        let mut mutated = false;
+        let mut kind = None;
+        for index in 0..self.others.len() {
+            let selection = self.others[index];
+            self.load_selection(selection);
+            let (changed, changed_kind) = op(self);
+            mutated |= changed;
+            kind = changed_kind;
+            self.others[index] = self.selection();
+        }
+
+        let primary = self.others.remove(primary_index);
+        self.load_selection(primary);
+        (mutated, kind)
+    }
+
+    fn apply_at_cursor(&mut self, action: Action) -> (bool, Option<EditKind>) {
+        self.clamp_cursor();
+
+        let before = self.cursor;
+        let mut mutated = false;
+        let mut kind = None;
+        match action {
+            Action::MoveLeft => {
+                if !self.collapse_selection(false) {
+                    self.move_left();
+                }
+            }
+            Action::MoveRight => {
+                if !self.collapse_selection(true) {
+                    self.move_right();
+                }
+            }
+            Action::MoveWordLeft => {
+                self.anchor = None;
+                self.move_word_left();
+            }
+            Action::MoveWordRight => {
+                self.anchor = None;
+                self.move_word_right();
+            }
+            Action::MoveUp => {
+                self.anchor = None;
+                self.move_up();
+            }
+            Action::MoveDown => {
+                self.anchor = None;
+                self.move_down();
+            }
+            Action::MoveVisualUp => {
+                self.anchor = None;
+                self.move_visual_up();
+            }
+            Action::MoveVisualDown => {
+                self.anchor = None;
+                self.move_visual_down();
+            }
+            Action::MoveVisualLineStart => {
+                self.anchor = None;
+                self.move_visual_line_start();
+            }
+            Action::MoveVisualLineEnd => {
+                self.anchor = None;
+                self.move_visual_line_end();
+            }
+            Action::MoveLineStart => {
+                self.anchor = None;
+                self.move_line_start();
+            }
+            Action::MoveLineEnd => {
+                self.anchor = None;
+                self.move_line_end();
+            }
+            Action::MoveFirstNonWhitespace => {
+                self.anchor = None;
+                self.move_first_non_whitespace();
+            }
+            Action::MoveBufferStart => {
+                self.anchor = None;
+                self.move_buffer_start();
+            }
+            Action::MoveBufferEnd => {
+                self.anchor = None;
+                self.move_buffer_end();
+            }
+            Action::GotoLine(row) => {
+                self.anchor = None;
+                self.cursor = Cursor::new(row, 0);
+                self.clamp_cursor();
+            }
+            Action::GotoPosition(position) => {
+                self.anchor = None;
+                self.cursor = position;
+                self.clamp_cursor();
+            }
+            Action::SelectLeft => self.extend_selection(Self::move_left),
+            Action::SelectRight => self.extend_selection(Self::move_right),
+            Action::SelectWordLeft => self.extend_selection(Self::move_word_left),
+            Action::SelectWordRight => self.extend_selection(Self::move_word_right),
+            Action::SelectUp => self.extend_selection(Self::move_up),
+            Action::SelectDown => self.extend_selection(Self::move_down),
+            Action::SelectVisualUp => self.extend_selection(Self::move_visual_up),
+            Action::SelectVisualDown => self.extend_selection(Self::move_visual_down),
+            Action::SelectVisualLineStart => self.extend_selection(Self::move_visual_line_start),
+            Action::SelectVisualLineEnd => self.extend_selection(Self::move_visual_line_end),
+            Action::SelectLineStart => self.extend_selection(Self::move_line_start),
+            Action::SelectLineEnd => self.extend_selection(Self::move_line_end),
+            Action::SelectFirstNonWhitespace => {
+                self.extend_selection(Self::move_first_non_whitespace)
+            }
+            Action::SelectBufferStart => self.extend_selection(Self::move_buffer_start),
+            Action::SelectBufferEnd => self.extend_selection(Self::move_buffer_end),
+            Action::Insert(ch) => {
+                let replaced = self.delete_selection();
+                // A lone '\r' is a line break too, so lines never hold one.
+                if matches!(ch, '\n' | '\r') {
+                    mutated = self.insert_newline();
+                } else {
+                    mutated = self.insert_char(ch);
+                    if !replaced {
+                        kind = Some(EditKind::Insert);
+                    }
+                }
+                mutated |= replaced;
+            }
+            Action::DeleteBackward => {
+                if self.delete_selection() {
+                    mutated = true;
+                } else {
+                    // Joining lines ends a run of backspaces.
+                    if before.col > 0 {
+                        kind = Some(EditKind::DeleteBackward);
+                    }
+                    mutated = self.delete_backward();
+                }
+            }
+            Action::DeleteForward => {
+                if self.delete_selection() {
+                    mutated = true;
+                } else {
+                    if before.col < self.buffer.line_len_chars(before.row) {
+                        kind = Some(EditKind::DeleteForward);
+                    }
+                    mutated = self.delete_forward();
+                }
+            }
+            Action::Newline => {
+                let replaced = self.delete_selection();
+                mutated = self.insert_newline() || replaced;
+            }
+            Action::BlockSelectLeft
+            | Action::BlockSelectRight
+            | Action::BlockSelectUp
+            | Action::BlockSelectDown
+            | Action::SelectRegister(_)
+            | Action::Copy
+            | Action::Cut
+            | Action::Paste
+            | Action::PasteBefore
+            | Action::KillWordForward
+            | Action::KillWordBackward
+            | Action::KillToLineEnd
+            | Action::Yank
+            | Action::YankPop
+            | Action::SelectAll
+            | Action::AddCursorAbove
+            | Action::AddCursorBelow
+            | Action::AddNextOccurrence
+            | Action::RemoveSecondaryCursors
+            | Action::Undo
+            | Action::Redo
+            | Action::Earlier
+            | Action::Later
+            | Action::PageUp
+            | Action::PageDown
+            | Action::HalfPageUp
+            | Action::HalfPageDown => {}
+        }
+
+        (mutated, kind)
+    }
+
+    // Inserts a whole string at every cursor in one pass, replacing any
+    // selection. "\r\n" and '\r' become '\n'. The insert is one undo step.
+    pub fn insert_str(&mut self, text: &str) {
+        self.clamp_cursor();
+        self.pending_register = None;
+        if self.buffer.is_read_only() {
+            return;
+        }
+
+        let before = self.history_cursors();
+        self.block = None;
+        let text = normalize_line_endings(text);
+        let (mutated, _) = self.for_each_cursor(|editor| {
+            let replaced = editor.delete_selection();
+            if text.is_empty() {
+                return (replaced, None);
+            }
+            editor.cursor = editor.insert_text(editor.cursor, &text);
+            (true, None)
+        });
+        self.record_edit(before, mutated, None);
+
+        self.kill_chain = false;
+        self.last_yank = None;
+        self.goal_cols.clear();
+        self.settle_selections();
+    }
+
+    pub fn visible_lines(&self) -> Vec<String> {
+        self.visual_rows()
+            .into_iter()
+            .map(|visual| {
+                let line = self.buffer.line(visual.row).unwrap_or_default();
+                if self.viewport.width == 0 {
+                    String::new()
+                } else if self.soft_wrap {
+                    let mut text = " ".repeat(self.wrap_origin(visual.start_col));
+                    text.extend(line.chars().take(visual.end_col).skip(visual.start_col));
+                    slice_line(&text, 0, self.viewport.width, self.tab_width)
+                } else {
+                    slice_line(
+                        line,
+                        self.viewport.col_offset,
+                        self.viewport.width,
+                        self.tab_width,
+                    )
+                }
+            })
+            .collect()
+    }
+
+    // Where each row of `visible_lines` comes from. Without soft wrap these
+    // are the visible buffer lines, limited to the chars the viewport shows.
+    pub fn visual_rows(&self) -> Vec<VisualRow> {
+        let start = self.viewport.row_offset;
+        if self.viewport.height == 0 || start >= self.buffer.line_count() {
+            return Vec::new();
+        }
+
+        let mut rows = Vec::with_capacity(self.viewport.height);
+        let mut skip = if self.soft_wrap {
+            self.viewport.wrap_offset
+        } else {
+            0
+        };
+        for row in start..self.buffer.line_count() {
+            let ranges = if self.soft_wrap {
+                self.wrap_rows(row)
+            } else {
+                let line = self.buffer.line(row).unwrap_or_default();
+                vec![visible_char_range(
+                    line,
+                    self.viewport.col_offset,
+                    self.viewport.width,
+                    self.tab_width,
+                )]
+            };
+            for (start_col, end_col) in ranges.into_iter().skip(skip) {
+                if rows.len() == self.viewport.height {
+                    return rows;
+                }
+                rows.push(VisualRow {
+                    row,
+                    start_col,
+                    end_col,
+                });
+            }
+            skip = 0;
+        }
+        rows
+    }
+
+    pub fn cursor_screen_pos(&self) -> (usize, usize) {
+        if self.soft_wrap {
+            let (sub_row, col) = self.visual_pos(self.cursor);
+            let top = (self.viewport.row_offset, self.viewport.wrap_offset);
+            let cursor = (self.cursor.row, sub_row);
+            let row = if cursor < top {
+                0
+            } else {
+                self.visual_distance(top, cursor, usize::MAX)
+            };
+            // The end of a line that exactly fills its last row is reported
+            // on that row's last cell rather than past the right edge.
+            let col = col.min(self.viewport.width.saturating_sub(1));
+            return (row, col);
+        }
+
+        (
+            self.cursor.row.saturating_sub(self.viewport.row_offset),
+            self.cursor_display_col()
+                .saturating_sub(self.viewport.col_offset),
+        )
+    }
+
+    // Char ranges of the visual rows of line `row`: the screen rows it wraps
+    // onto with soft wrap, else the whole line.
+    fn wrap_rows(&self, row: usize) -> Vec<(usize, usize)> {
+        let line = self.buffer.line(row).unwrap_or_default();
+        if !self.soft_wrap {
+            return vec![(0, self.buffer.line_len_chars(row))];
+        }
+        wrap_line(
+            line,
+            self.viewport.width,
+            self.tab_width,
+            self.wrap_at_words,
+            self.wrap_indent,
+        )
+    }
+
+    // Display column a wrapped row starting at char `start_col` begins at.
+    fn wrap_origin(&self, start_col: usize) -> usize {
+        if start_col == 0 {
+            0
+        } else {
+            wrap_indent(self.wrap_indent, self.viewport.width)
+        }
+    }
+
+    // The visual row of `at`'s line holding `at`, and `at`'s screen column
+    // on it (before horizontal scrolling).
+    fn visual_pos(&self, at: Cursor) -> (usize, usize) {
+        let rows = self.wrap_rows(at.row);
+        let sub_row = rows
+            .iter()
+            .rposition(|&(start, _)| start <= at.col)
+            .unwrap_or(0);
+        let start_col = rows[sub_row].0;
+        let col = display_col_from(
+            self.buffer.line(at.row).unwrap_or_default(),
+            start_col,
+            at.col,
+            self.wrap_origin(start_col),
+            self.tab_width,
+        );
+        (sub_row, col)
+    }
+
+    // Char column on visual row `sub_row` of line `row` drawn under screen
+    // column `goal`, as `col_under` places it.
+    fn col_at_screen(&self, row: usize, sub_row: usize, goal: usize) -> usize {
+        let rows = self.wrap_rows(row);
+        let sub_row = sub_row.min(rows.len() - 1);
+        let range = rows[sub_row];
+        let origin = self.wrap_origin(range.0);
+        self.col_under(row, range, origin, goal, sub_row + 1 == rows.len())
+    }
+
+    // Char column among chars `start..end` of line `row`, drawn from display
+    // column `origin`, whose cells cover display column `goal`. Past the
+    // last char this is `end` if `at_line_end`, else the last char. Never
+    // lands inside a zero-width sequence.
+    fn col_under(
+        &self,
+        row: usize,
+        (start, end): (usize, usize),
+        origin: usize,
+        goal: usize,
+        at_line_end: bool,
+    ) -> usize {
+        let line = self.buffer.line(row).unwrap_or_default();
+        let mut display = origin;
+        let mut last = start;
+        for (idx, ch) in line.chars().enumerate().take(end).skip(start) {
+            let ch_width = cell_width(ch, display, self.tab_width);
+            if ch_width > 0 {
+                if goal < display + ch_width {
+                    return self.cluster_start(row, idx);
+                }
+                last = idx;
+            }
+            display += ch_width;
+        }
+        if at_line_end {
+            end
+        } else {
+            self.cluster_start(row, last)
+        }
+    }
+
+    // `col`, moved back to the start of the grapheme cluster holding it when
+    // clusters are on.
+    fn cluster_start(&self, row: usize, col: usize) -> usize {
+        let line = self.buffer.line(row).unwrap_or_default();
+        let byte = self.buffer.char_to_byte(row, col);
+        if !self.grapheme_clusters || byte >= line.len() {
+            return col;
+        }
+        let start = prev_grapheme_boundary(line, next_grapheme_boundary(line, byte));
+        self.buffer.byte_to_char(row, start)
+    }
+
+    // The goal column of the cursor's run of vertical moves of this kind,
+    // or `current` when a run starts here.
+    fn take_goal_col(&mut self, visual: bool, current: usize) -> usize {
+        let head = self.cursor;
+        match self
+            .goal_cols
+            .iter()
+            .position(|goal| goal.head == head && goal.visual == visual)
+        {
+            Some(index) => self.goal_cols.remove(index).col,
+            None => current,
+        }
+    }
+
+    fn keep_goal_col(&mut self, visual: bool, col: usize) {
+        self.goal_cols.push(GoalCol {
+            head: self.cursor,
+            visual,
+            col,
+        });
+    }
+
+    // Visual rows from `from` down to `to` (both `(row, wrapped row)`,
+    // `from <= to`), counting at most about `limit`.
+    fn visual_distance(&self, from: (usize, usize), to: (usize, usize), limit: usize) -> usize {
+        if from.0 == to.0 {
+            return to.1 - from.1;
+        }
+
+        let mut distance = self.wrap_rows(from.0).len() - from.1;
+        for row in from.0 + 1..to.0 {
+            if distance >= limit {
+                return distance;
+            }
+            distance += self.wrap_rows(row).len();
+        }
+        distance + to.1
+    }
+
+    // The visual row `count` rows below `from`, stopping at the last one.
+    fn visual_row_forward(&self, from: (usize, usize), count: usize) -> (usize, usize) {
+        let last_row = self.buffer.line_count().saturating_sub(1);
+        let (mut row, mut sub_row) = from;
+        let mut count = count;
+        loop {
+            let last_sub_row = self.wrap_rows(row).len() - 1;
+            if count <= last_sub_row - sub_row || row >= last_row {
+                return (row, (sub_row + count).min(last_sub_row));
+            }
+            count -= last_sub_row - sub_row + 1;
+            row += 1;
+            sub_row = 0;
+        }
+    }
+
+    // The visual row `count` rows above `from`, stopping at the first one.
+    fn visual_row_back(&self, from: (usize, usize), count: usize) -> (usize, usize) {
+        let (mut row, mut sub_row) = from;
+        let mut count = count;
+        while count > sub_row && row > 0 {
+            count -= sub_row + 1;
+            row -= 1;
+            sub_row = self.wrap_rows(row).len() - 1;
+        }
+        (row, sub_row.saturating_sub(count))
+    }
+
+    // Only the part of the line past its plain prefix is measured, and
+    // without tabs the storage can total its width, so the cursor stays
+    // cheap to place on long lines.
+    fn cursor_display_col(&self) -> usize {
+        let Cursor { row, col } = self.cursor;
+        let plain = self.buffer.plain_prefix_chars(row);
+        if col <= plain {
+            return col;
+        }
+        if let Some(width) = self.buffer.untabbed_width(row, plain, col) {
+            return plain + width;
+        }
+
+        let text = self.buffer.text_range(Cursor::new(row, plain), self.cursor);
+        display_end(&text, plain, self.tab_width)
+    }
+
+    // Cells taken by the char under the cursor, at least one. A tab only
+    // needs its first cell on screen.
+    fn cursor_char_width(&self) -> usize {
+        let Cursor { row, col } = self.cursor;
+        self.buffer
+            .text_range(self.cursor, Cursor::new(row, col + 1))
+            .chars()
+            .next()
+            .map_or(1, char_width)
+            .max(1)
+    }
+
+    // Every cursor, primary first, as the undo history records them.
+    fn history_cursors(&self) -> Vec<Cursor> {
+        let mut cursors = Vec::with_capacity(self.others.len() + 1);
+        cursors.push(self.cursor);
+        cursors.extend(self.others.iter().map(|selection| selection.head));
+        cursors
+    }
+
+    fn restore_history_cursors(&mut self, cursors: Option<Vec<Cursor>>) -> bool {
+        let Some((&primary, others)) = cursors.as_deref().and_then(<[Cursor]>::split_first) else {
+            return false;
+        };
+
+        self.cursor = primary;
+        self.anchor = None;
+        self.others = others.iter().copied().map(Selection::caret).collect();
+        self.block = None;
+        self.kill_chain = false;
+        self.last_yank = None;
+        self.dirty = true;
+        self.clamp_cursor();
+        self.merge_selections();
+        self.ensure_cursor_visible();
+        true
+    }
+
+    fn clamp_cursor(&mut self) {
+        self.cursor = self.clamp_position(self.cursor);
+        self.anchor = self.anchor.map(|anchor| self.clamp_position(anchor));
+        for index in 0..self.others.len() {
+            self.others[index] = self.others[index].map(|position| self.clamp_position(position));
+        }
+    }
+
+    fn clamp_position(&self, position: Cursor) -> Cursor {
+        let line_count = self.buffer.line_count();
+        let row = position.row.min(line_count.saturating_sub(1));
+        let col = position.col.min(self.buffer.line_len_chars(row));
+        Cursor::new(row, col)
+    }
+
+    fn edits_text(action: Action) -> bool {
+        matches!(
+            action,
+            Action::Cut
+                | Action::Paste
+                | Action::PasteBefore
+                | Action::KillWordForward
+                | Action::KillWordBackward
+                | Action::KillToLineEnd
+                | Action::Yank
+                | Action::YankPop
+                | Action::Insert(_)
+                | Action::DeleteBackward
+                | Action::DeleteForward
+                | Action::Newline
+        )
+    }
+
+    fn is_vertical_motion(action: Action) -> bool {
+        matches!(
+            action,
+            Action::MoveUp
+                | Action::MoveDown
+                | Action::SelectUp
+                | Action::SelectDown
+                | Action::MoveVisualUp
+                | Action::MoveVisualDown
+                | Action::SelectVisualUp
+                | Action::SelectVisualDown
+                | Action::PageUp
+                | Action::PageDown
+                | Action::HalfPageUp
+                | Action::HalfPageDown
+        )
+    }
+
+    fn keeps_block(action: Action) -> bool {
+        match action {
+            Action::BlockSelectLeft
+            | Action::BlockSelectRight
+            | Action::BlockSelectUp
+            | Action::BlockSelectDown
+            | Action::DeleteBackward
+            | Action::DeleteForward
+            | Action::SelectRegister(_)
+            | Action::Copy
+            | Action::Cut => true,
+            Action::Insert(ch) => !matches!(ch, '\n' | '\r'),
+            _ => false,
+        }
+    }
+
+    fn extend_block(&mut self, action: Action) {
+        self.anchor = None;
+        self.others.clear();
+        let mut block = self
+            .block
+            .unwrap_or_else(|| BlockSelection::new(self.cursor, self.cursor));
+
+        let last_row = self.buffer.line_count().saturating_sub(1);
+        let head = &mut block.head;
+        match action {
+            Action::BlockSelectLeft => head.col = head.col.saturating_sub(1),
+            Action::BlockSelectRight => head.col += 1,
+            Action::BlockSelectUp => head.row = head.row.saturating_sub(1),
+            Action::BlockSelectDown => head.row = (head.row + 1).min(last_row),
+            _ => {}
+        }
+
+        self.block = Some(block);
+        self.cursor = block.head;
+    }
+
+    // Replaces the block's columns with `ch` on every row, leaving a
+    // zero-width block just after the inserted character.
+    fn block_insert(&mut self, ch: char) -> bool {
+        let Some(block) = self.block else {
+            return false;
+        };
+
+        self.remove_block_columns(block);
+        let left = block.left_col();
+        let mut encoded = [0; 4];
+        let text = ch.encode_utf8(&mut encoded);
+        for row in block.top_row()..=block.bottom_row() {
+            let at = self.pad_line_to(row, left);
+            self.insert_text(at, text);
+        }
+
+        self.set_block_cursor(block.with_col(left + 1));
+        true
+    }
+
+    // Removes the block's columns on every row, or with a zero-width block
+    // the character before (or at, going forward) its column.
+    fn block_delete(&mut self, forward: bool) -> bool {
+        let Some(block) = self.block else {
+            return false;
+        };
+
+        let left = block.left_col();
+        if block.right_col() > left {
+            let mutated = self.remove_block_columns(block);
+            self.set_block_cursor(block.with_col(left));
+            return mutated;
+        }
+
+        let (start_col, new_col) = if forward {
+            (left, left)
+        } else if left > 0 {
+            (left - 1, left - 1)
+        } else {
+            return false;
+        };
+
+        let mut mutated = false;
+        for row in block.top_row()..=block.bottom_row() {
+            if start_col < self.buffer.line_len_chars(row) {
+                self.remove_text(Cursor::new(row, start_col), Cursor::new(row, start_col + 1));
+                mutated = true;
+            }
+        }
+
+        self.set_block_cursor(block.with_col(new_col));
+        mutated
+    }
+
+    fn remove_block_columns(&mut self, block: BlockSelection) -> bool {
+        let mut mutated = false;
+        for row in block.top_row()..=block.bottom_row() {
+            let line_len = self.buffer.line_len_chars(row);
+            let left = block.left_col().min(line_len);
+            let right = block.right_col().min(line_len);
+            if left < right {
+                self.remove_text(Cursor::new(row, left), Cursor::new(row, right));
+                mutated = true;
+            }
+        }
+        mutated
+    }
+
+    // Rows shorter than the widest one are padded with spaces when the line
+    // has text past the paste column, so that text stays aligned.
+    fn insert_block<R: AsRef<str>>(&mut self, rows: &[R]) {
+        let origin = self.cursor;
+        let width = rows
+            .iter()
+            .map(|text| text.as_ref().chars().count())
+            .max()
+            .unwrap_or(0);
+        for (offset, text) in rows.iter().enumerate() {
+            let row = origin.row + offset;
+            if row >= self.buffer.line_count() {
+                let end = self.buffer.end_position();
+                self.insert_text(end, "\n");
+            }
+            let text = text.as_ref();
+            let len = text.chars().count();
+            let at = self.pad_line_to(row, origin.col);
+            if len < width && self.buffer.line_len_chars(row) > origin.col {
+                let padded = format!("{text}{}", " ".repeat(width - len));
+                self.insert_text(at, &padded);
+            } else {
+                self.insert_text(at, text);
+            }
+        }
+        self.cursor = origin;
+    }
+
+    // What `Copy`/`Cut` take: the block, the non-empty selections joined by
+    // '\n', or the lines under the cursors when nothing is selected.
+    fn copy_content(&self) -> RegisterContent {
+        if let Some(rows) = self.block_text() {
+            return RegisterContent::blockwise(&rows);
+        }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Editor {
-    buffer: Buffer,
-    cursor: Cursor,
-    viewport: Viewport,
-    dirty: bool,
-}
+        let selections = self.selections();
+        if selections.iter().all(Selection::is_empty) {
+            let mut text = String::new();
+            let mut last_row = None;
+            for selection in &selections {
+                let row = selection.head.row;
+                if last_row == Some(row) {
+                    continue;
+                }
+                last_row = Some(row);
+                text.push_str(self.buffer.line(row).unwrap_or_default());
+                text.push('\n');
+            }
+            return RegisterContent::linewise(text);
+        }
 
-impl Editor {
-    pub fn new(buffer: Buffer, viewport: Viewport) -> Self {
-        let mut editor = Self {
-            buffer,
-            cursor: Cursor::new(0, 0),
-            viewport,
-            dirty: false,
+        let texts: Vec<String> = selections
+            .iter()
+            .filter(|selection| !selection.is_empty())
+            .map(|selection| self.buffer.text_range(selection.start(), selection.end()))
+            .collect();
+        RegisterContent::charwise(texts.join("\n"))
+    }
+
+    fn cut(&mut self, register: Option<char>) -> bool {
+        let content = self.copy_content();
+        let kind = content.kind;
+        self.registers.record(register, content, true);
+
+        match kind {
+            // A zero-width block copies empty rows and removes nothing.
+            RegisterKind::Blockwise => match self.block {
+                Some(block) if block.right_col() > block.left_col() => {
+                    let mutated = self.remove_block_columns(block);
+                    self.set_block_cursor(block.with_col(block.left_col()));
+                    mutated
+                }
+                _ => false,
+            },
+            RegisterKind::Linewise => {
+                let mut rows = Vec::new();
+                self.others.retain(|selection| {
+                    let keep = !rows.contains(&selection.head.row);
+                    rows.push(selection.head.row);
+                    keep
+                });
+                if self
+                    .others
+                    .iter()
+                    .any(|other| other.head.row == self.cursor.row)
+                {
+                    self.others
+                        .retain(|other| other.head.row != self.cursor.row);
+                }
+                self.for_each_cursor(|editor| (editor.delete_line(), None))
+                    .0
+            }
+            RegisterKind::Charwise => {
+                self.for_each_cursor(|editor| (editor.delete_selection(), None))
+                    .0
+            }
+        }
+    }
+
+    fn delete_line(&mut self) -> bool {
+        let row = self.cursor.row;
+        let line_len = self.buffer.line_len_chars(row);
+        let (start, end) = if row + 1 < self.buffer.line_count() {
+            (Cursor::new(row, 0), Cursor::new(row + 1, 0))
+        } else if row > 0 {
+            let prev_len = self.buffer.line_len_chars(row - 1);
+            (Cursor::new(row - 1, prev_len), Cursor::new(row, line_len))
+        } else {
+            (Cursor::new(row, 0), Cursor::new(row, line_len))
         };
 
-        editor.clamp_cursor();
-        editor.ensure_cursor_visible();
-        editor
+        let removed = self.remove_text(start, end);
+        self.anchor = None;
+        self.cursor = Cursor::new(start.row, 0);
+        !removed.is_empty()
     }
 
-    pub fn buffer(&self) -> &Buffer {
-        &self.buffer
+    // Kills run at the primary cursor only. Consecutive kills extend the most
+    // recent kill-ring entry instead of starting a new one.
+    fn kill(&mut self, action: Action) -> bool {
+        self.anchor = None;
+        self.others.clear();
+
+        let origin = self.cursor;
+        let (start, end) = match action {
+            Action::KillWordForward => {
+                self.move_word_right();
+                (origin, self.cursor)
+            }
+            Action::KillWordBackward => {
+                self.move_word_left();
+                (self.cursor, origin)
+            }
+            _ => {
+                let line_len = self.buffer.line_len_chars(origin.row);
+                if origin.col < line_len {
+                    (origin, Cursor::new(origin.row, line_len))
+                } else if origin.row + 1 < self.buffer.line_count() {
+                    (origin, Cursor::new(origin.row + 1, 0))
+                } else {
+                    (origin, origin)
+                }
+            }
+        };
+
+        let extend = self.kill_chain;
+        self.kill_chain = true;
+        self.cursor = start;
+        if start == end {
+            return false;
+        }
+
+        let text = self.remove_text(start, end);
+        if extend {
+            self.kill_ring
+                .extend(&text, action == Action::KillWordBackward);
+        } else {
+            self.kill_ring.push(text);
+        }
+        true
     }
 
-    pub fn cursor(&self) -> Cursor {
-        self.cursor
+    fn yank(&mut self) -> bool {
+        let Some(text) = self.kill_ring.get(0).map(str::to_string) else {
+            return false;
+        };
+
+        self.anchor = None;
+        self.others.clear();
+        let start = self.cursor;
+        let end = self.insert_text(start, &text);
+        self.cursor = end;
+        self.last_yank = Some(YankState {
+            start,
+            end,
+            index: 0,
+        });
+        true
     }
 
-    pub fn viewport(&self) -> Viewport {
-        self.viewport
+    // Replaces the text of the previous `Yank`/`YankPop` with the next older
+    // kill-ring entry, cycling back to the newest after the oldest.
+    fn yank_pop(&mut self) -> bool {
+        let Some(state) = self.last_yank else {
+            return false;
+        };
+        if self.kill_ring.len() < 2 {
+            return false;
+        }
+
+        let index = (state.index + 1) % self.kill_ring.len();
+        let text = self.kill_ring.get(index).unwrap_or_default().to_string();
+        self.remove_text(state.start, state.end);
+        let end = self.insert_text(state.start, &text);
+        self.cursor = end;
+        self.last_yank = Some(YankState {
+            start: state.start,
+            end,
+            index,
+        });
+        true
     }
 
-    pub fn set_viewport(&mut self, viewport: Viewport) {
-        self.viewport = viewport;
-        self.ensure_cursor_visible();
+    // Charwise content goes at the cursor (replacing a selection); linewise
+    // content goes below the cursor's line, or above it for `PasteBefore`;
+    // blockwise content is inserted as a rectangle at the cursor.
+    fn paste(&mut self, content: &RegisterContent, before: bool) -> bool {
+        let replaced = self.delete_selection();
+        match content.kind {
+            RegisterKind::Charwise => {
+                if content.text.is_empty() {
+                    return replaced;
+                }
+                let at = self.cursor;
+                let end = self.insert_text(at, &content.text);
+                self.cursor = if before { at } else { end };
+            }
+            RegisterKind::Linewise => {
+                let mut text = content.text.clone();
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                let row = self.cursor.row;
+                if before {
+                    self.insert_text(Cursor::new(row, 0), &text);
+                    self.cursor = Cursor::new(row, 0);
+                } else if row + 1 < self.buffer.line_count() {
+                    self.insert_text(Cursor::new(row + 1, 0), &text);
+                    self.cursor = Cursor::new(row + 1, 0);
+                } else {
+                    text.pop();
+                    text.insert(0, '\n');
+                    let end = Cursor::new(row, self.buffer.line_len_chars(row));
+                    self.insert_text(end, &text);
+                    self.cursor = Cursor::new(row + 1, 0);
+                }
+            }
+            RegisterKind::Blockwise => {
+                let rows: Vec<&str> = content.text.split('\n').collect();
+                self.insert_block(&rows);
+            }
+        }
+        true
     }
 
-    pub fn is_dirty(&self) -> bool {
-        self.dirty
+    fn set_block_cursor(&mut self, block: BlockSelection) {
+        self.block = Some(block);
+        self.cursor = block.head;
     }
 
-    pub fn reset_dirty(&mut self) {
-        self.dirty = false;
+    // Pads `row` with spaces so that `col` exists, returning that position.
+    fn pad_line_to(&mut self, row: usize, col: usize) -> Cursor {
+        let line_len = self.buffer.line_len_chars(row);
+        if line_len < col {
+            let padding = " ".repeat(col - line_len);
+            self.insert_text(Cursor::new(row, line_len), &padding);
+        }
+        Cursor::new(row, col)
     }
 
-    pub fn apply(&mut self, action: Action) {
-        self.clamp_cursor();
+    fn load_selection(&mut self, selection: Selection) {
+        self.cursor = selection.head;
+        self.anchor = (!selection.is_empty()).then_some(selection.anchor);
+    }
 
-        let mut mutated = false;
-        match action {
-            Action::MoveLeft => self.move_left(),
-            Action::MoveRight => self.move_right(),
-            Action::MoveWordLeft => self.move_word_left(),
-            Action::MoveWordRight => self.move_word_right(),
-            Action::MoveUp => self.move_up(),
-            Action::MoveDown => self.move_down(),
-            Action::Insert(ch) => {
-                if ch == '\n' {
-                    mutated = self.insert_newline();
-                } else {
-                    mutated = self.insert_char(ch);
+    // Sorts the selections and merges any that overlap or share a caret
+    // position. The merged selection containing the primary head stays primary.
+    fn merge_selections(&mut self) {
+        if self.others.is_empty() {
+            return;
+        }
+
+        let primary_head = self.cursor;
+        let mut selections = std::mem::take(&mut self.others);
+        selections.push(self.selection());
+        selections.sort_by_key(Selection::start);
+
+        let mut merged: Vec<Selection> = Vec::with_capacity(selections.len());
+        for selection in selections {
+            if let Some(last) = merged.last_mut() {
+                let touches = selection.start() < last.end()
+                    || (selection.start() == last.end()
+                        && (selection.is_empty() || last.is_empty()));
+                if touches || selection.start() == last.start() {
+                    let start = last.start();
+                    let end = last.end().max(selection.end());
+                    *last = if last.anchor <= last.head {
+                        Selection::new(start, end)
+                    } else {
+                        Selection::new(end, start)
+                    };
+                    continue;
                 }
             }
-            Action::DeleteBackward => mutated = self.delete_backward(),
-            Action::DeleteForward => mutated = self.delete_forward(),
-            Action::Newline => mutated = self.insert_newline(),
+            merged.push(selection);
         }
 
-        if mutated {
-            self.dirty = true;
-        }
+        let primary_index = merged
+            .iter()
+            .position(|selection| {
+                selection.start() <= primary_head && primary_head <= selection.end()
+            })
+            .unwrap_or_default();
+        let primary = merged.remove(primary_index);
+        self.others = merged;
+        self.load_selection(primary);
+    }
 
-        self.clamp_cursor();
-        self.ensure_cursor_visible();
+    fn add_cursor_vertical(&mut self, below: bool) {
+        let selections = self.selections();
+        let edge = if below {
+            selections.iter().map(|selection| selection.head).max()
+        } else {
+            selections.iter().map(|selection| selection.head).min()
+        };
+        let Some(edge) = edge else {
+            return;
+        };
+
+        let row = if below {
+            if edge.row + 1 >= self.buffer.line_count() {
+                return;
+            }
+            edge.row + 1
+        } else {
+            let Some(row) = edge.row.checked_sub(1) else {
+                return;
+            };
+            row
+        };
+
+        let col = self.cursor.col.min(self.buffer.line_len_chars(row));
+        self.others.push(self.selection());
+        self.load_selection(Selection::caret(Cursor::new(row, col)));
     }
 
-    pub fn visible_lines(&self) -> Vec<String> {
-        if self.viewport.height == 0 {
-            return Vec::new();
+    // With an empty primary selection, selects the word under the cursor.
+    // Otherwise adds a selection on the next occurrence of the selected text
+    // after the last selection, wrapping around the buffer.
+    fn add_next_occurrence(&mut self) {
+        let selection = self.selection();
+        if selection.is_empty() {
+            if let Some((start, end)) = self.word_at(self.cursor) {
+                self.load_selection(Selection::new(start, end));
+            }
+            return;
         }
 
-        let start = self.viewport.row_offset;
-        if start >= self.buffer.line_count() {
-            return Vec::new();
+        let needle = self.buffer.text_range(selection.start(), selection.end());
+        let selections = self.selections();
+        let from = selections
+            .iter()
+            .map(Selection::end)
+            .max()
+            .unwrap_or(selection.end());
+        let Some(start) = find_next(&self.buffer, &needle, from) else {
+            return;
+        };
+        if selections.iter().any(|existing| existing.start() == start) {
+            return;
         }
 
-        let end = (start + self.viewport.height).min(self.buffer.line_count());
-        let mut lines = Vec::with_capacity(end - start);
-        for row in start..end {
-            let line = self.buffer.line(row).unwrap_or_default();
-            if self.viewport.width == 0 {
-                lines.push(String::new());
-            } else {
-                lines.push(slice_line(
-                    line,
-                    self.viewport.col_offset,
-                    self.viewport.width,
-                ));
+        self.others.push(selection);
+        self.load_selection(Selection::new(start, text_end(start, &needle)));
+    }
+
+    fn word_at(&self, position: Cursor) -> Option<(Cursor, Cursor)> {
+        let line = self.buffer.line(position.row)?;
+        let byte_col = self.buffer.char_to_byte(position.row, position.col);
+
+        let mut start = byte_col;
+        while let Some(prev) = line[..start].chars().next_back() {
+            if !is_word_char(prev) {
+                break;
+            }
+            start -= prev.len_utf8();
+        }
+
+        let mut end = byte_col;
+        while let Some(next) = line[end..].chars().next() {
+            if !is_word_char(next) {
+                break;
             }
+            end += next.len_utf8();
+        }
+
+        if start == end {
+            return None;
         }
-        lines
+
+        let start_col = self.buffer.byte_to_char(position.row, start);
+        let end_col = self.buffer.byte_to_char(position.row, end);
+        Some((
+            Cursor::new(position.row, start_col),
+            Cursor::new(position.row, end_col),
+        ))
     }
 
-    pub fn cursor_screen_pos(&self) -> (usize, usize) {
-        (
-            self.cursor.row.saturating_sub(self.viewport.row_offset),
-            self.cursor.col.saturating_sub(self.viewport.col_offset),
-        )
+    fn extend_selection(&mut self, motion: fn(&mut Self)) {
+        let anchor = self.anchor.unwrap_or(self.cursor);
+        motion(self);
+        self.anchor = (anchor != self.cursor).then_some(anchor);
     }
 
-    fn clamp_cursor(&mut self) {
-        if self.buffer.lines.is_empty() {
-            self.buffer.lines.push(String::new());
-        }
-        let line_count = self.buffer.line_count();
-        if self.cursor.row >= line_count {
-            self.cursor.row = line_count.saturating_sub(1);
+    // Drops the selection, leaving the cursor at its start or end. Returns
+    // whether there was a non-empty selection to collapse.
+    fn collapse_selection(&mut self, to_end: bool) -> bool {
+        let selection = self.selection();
+        self.anchor = None;
+        if selection.is_empty() {
+            return false;
         }
-        let max_col = self.buffer.line_len_chars(self.cursor.row);
-        if self.cursor.col > max_col {
-            self.cursor.col = max_col;
+
+        self.cursor = if to_end {
+            selection.end()
+        } else {
+            selection.start()
+        };
+        true
+    }
+
+    fn select_all(&mut self) {
+        let last_row = self.buffer.line_count().saturating_sub(1);
+        self.anchor = Some(Cursor::new(0, 0));
+        self.cursor = Cursor::new(last_row, self.buffer.line_len_chars(last_row));
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.anchor = None;
+        if selection.is_empty() {
+            return false;
         }
+
+        self.remove_text(selection.start(), selection.end());
+        self.cursor = selection.start();
+        true
     }
 
     fn ensure_cursor_visible(&mut self) {
+        if self.soft_wrap {
+            self.ensure_cursor_visible_wrapped();
+            return;
+        }
+
+        self.viewport.wrap_offset = 0;
         if self.viewport.height == 0 || self.cursor.row < self.viewport.row_offset {
             self.viewport.row_offset = self.cursor.row;
         } else if self.cursor.row >= self.viewport.row_offset + self.viewport.height {
             self.viewport.row_offset = self.cursor.row + 1 - self.viewport.height;
         }
 
-        if self.viewport.width == 0 || self.cursor.col < self.viewport.col_offset {
-            self.viewport.col_offset = self.cursor.col;
-        } else if self.cursor.col >= self.viewport.col_offset + self.viewport.width {
-            self.viewport.col_offset = self.cursor.col + 1 - self.viewport.width;
+        let col = self.cursor_display_col();
+        let end = col + self.cursor_char_width();
+        if self.viewport.width == 0 || col < self.viewport.col_offset {
+            self.viewport.col_offset = col;
+        } else if end > self.viewport.col_offset + self.viewport.width {
+            self.viewport.col_offset = end.saturating_sub(self.viewport.width).min(col);
+        }
+    }
+
+    // Scrolls by visual rows so the cursor's wrapped row is on screen.
+    fn ensure_cursor_visible_wrapped(&mut self) {
+        self.viewport.col_offset = 0;
+        let cursor = (self.cursor.row, self.visual_pos(self.cursor).0);
+        let mut top = (self.viewport.row_offset, self.viewport.wrap_offset);
+        if top.0 < self.buffer.line_count() {
+            top.1 = top.1.min(self.wrap_rows(top.0).len() - 1);
+        }
+
+        let height = self.viewport.height;
+        if height == 0 || cursor < top {
+            top = cursor;
+        } else if self.visual_distance(top, cursor, height) >= height {
+            top = self.visual_row_back(cursor, height - 1);
+        }
+        (self.viewport.row_offset, self.viewport.wrap_offset) = top;
+    }
+
+    // Column of the cluster (or char) boundary before `col` in `row`. Only
+    // chars near `col` are read, widening the window until it starts at a
+    // certain cluster boundary.
+    fn prev_col(&self, row: usize, col: usize) -> usize {
+        if !self.grapheme_clusters {
+            return col.saturating_sub(1);
+        }
+        let mut context = CLUSTER_CONTEXT;
+        loop {
+            let from = col.saturating_sub(context);
+            let text = self
+                .buffer
+                .text_range(Cursor::new(row, from), Cursor::new(row, col));
+            let last = text.chars().next_back().map_or(0, char::len_utf8);
+            if from == 0 || has_cluster_start_before(&text, text.len() - last) {
+                let byte = prev_grapheme_boundary(&text, text.len());
+                return from + text[..byte].chars().count();
+            }
+            context *= 2;
+        }
+    }
+
+    // Column of the cluster (or char) boundary after `col` in `row`, read
+    // from a window around `col` like `prev_col`.
+    fn next_col(&self, row: usize, col: usize) -> usize {
+        if !self.grapheme_clusters {
+            return col + 1;
+        }
+        let len = self.buffer.line_len_chars(row);
+        if col >= len {
+            return len;
+        }
+
+        let mut context = CLUSTER_CONTEXT;
+        loop {
+            let from = col.saturating_sub(context);
+            let to = (col + context).min(len);
+            let text = self
+                .buffer
+                .text_range(Cursor::new(row, from), Cursor::new(row, to));
+            let byte = char_to_byte_index(&text, col - from);
+            let boundary = next_grapheme_boundary(&text, byte);
+            if (from == 0 || has_cluster_start_before(&text, byte))
+                && (boundary < text.len() || to == len)
+            {
+                return from + text[..boundary].chars().count();
+            }
+            context *= 2;
         }
     }
 
     fn move_left(&mut self) {
         if self.cursor.col > 0 {
-            self.cursor.col -= 1;
+            self.cursor.col = self.prev_col(self.cursor.row, self.cursor.col);
             return;
         }
 
@@ -166,10 +1788,7 @@ impl Editor {
             row -= 1;
             byte_col = self.buffer.line(row).map_or(0, str::len);
         } else {
-            byte_col = self
-                .buffer
-                .line(row)
-                .map_or(0, |line| char_to_byte_index(line, self.cursor.col));
+            byte_col = self.buffer.char_to_byte(row, self.cursor.col);
         }
 
         // Skip whitespace first.
@@ -221,12 +1840,12 @@ impl Editor {
             return;
         };
 
-        if ch.is_alphanumeric() || ch == '_' {
+        if is_word_char(ch) {
             while byte_col > 0 {
                 let Some(prev) = line[..byte_col].chars().next_back() else {
                     break;
                 };
-                if prev.is_alphanumeric() || prev == '_' {
+                if is_word_char(prev) {
                     byte_col -= prev.len_utf8();
                 } else {
                     break;
@@ -237,15 +1856,12 @@ impl Editor {
         }
 
         self.cursor.row = row;
-        self.cursor.col = line[..byte_col].chars().count();
+        self.cursor.col = self.buffer.byte_to_char(row, byte_col);
     }
 
     fn move_word_right(&mut self) {
         let mut row = self.cursor.row;
-        let mut byte_col = self
-            .buffer
-            .line(row)
-            .map_or(0, |line| char_to_byte_index(line, self.cursor.col));
+        let mut byte_col = self.buffer.char_to_byte(row, self.cursor.col);
 
         // If we are at end of line, move to the next line before token scanning.
         if let Some(line) = self.buffer.line(row)
@@ -269,13 +1885,13 @@ impl Editor {
                     continue;
                 }
                 self.cursor.row = row;
-                self.cursor.col = line.chars().count();
+                self.cursor.col = self.buffer.line_len_chars(row);
                 return;
             }
 
             let Some(ch) = line[byte_col..].chars().next() else {
                 self.cursor.row = row;
-                self.cursor.col = line.chars().count();
+                self.cursor.col = self.buffer.line_len_chars(row);
                 return;
             };
 
@@ -296,16 +1912,16 @@ impl Editor {
 
         let Some(ch) = line[byte_col..].chars().next() else {
             self.cursor.row = row;
-            self.cursor.col = line.chars().count();
+            self.cursor.col = self.buffer.line_len_chars(row);
             return;
         };
 
-        if ch.is_alphanumeric() || ch == '_' {
+        if is_word_char(ch) {
             while byte_col < line.len() {
                 let Some(next) = line[byte_col..].chars().next() else {
                     break;
                 };
-                if next.is_alphanumeric() || next == '_' {
+                if is_word_char(next) {
                     byte_col += next.len_utf8();
                 } else {
                     break;
@@ -316,13 +1932,13 @@ impl Editor {
         }
 
         self.cursor.row = row;
-        self.cursor.col = line[..byte_col].chars().count();
+        self.cursor.col = self.buffer.byte_to_char(row, byte_col);
     }
 
     fn move_right(&mut self) {
         let line_len = self.buffer.line_len_chars(self.cursor.row);
         if self.cursor.col < line_len {
-            self.cursor.col += 1;
+            self.cursor.col = self.next_col(self.cursor.row, self.cursor.col);
             return;
         }
 
@@ -333,62 +1949,167 @@ impl Editor {
     }
 
     fn move_up(&mut self) {
-        if self.cursor.row == 0 {
-            return;
+        let goal = self.take_goal_col(false, self.cursor_display_col());
+        if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+            self.cursor.col = self.col_at_display(self.cursor.row, goal);
         }
+        self.keep_goal_col(false, goal);
+    }
 
-        self.cursor.row -= 1;
-        let line_len = self.buffer.line_len_chars(self.cursor.row);
-        if self.cursor.col > line_len {
-            self.cursor.col = line_len;
+    fn move_down(&mut self) {
+        let goal = self.take_goal_col(false, self.cursor_display_col());
+        if self.cursor.row + 1 < self.buffer.line_count() {
+            self.cursor.row += 1;
+            self.cursor.col = self.col_at_display(self.cursor.row, goal);
         }
+        self.keep_goal_col(false, goal);
     }
 
-    fn move_down(&mut self) {
-        if self.cursor.row + 1 >= self.buffer.line_count() {
-            return;
+    fn move_line_start(&mut self) {
+        self.cursor.col = 0;
+    }
+
+    fn move_line_end(&mut self) {
+        self.cursor.col = self.buffer.line_len_chars(self.cursor.row);
+    }
+
+    // Smart Home: goes to the first non-whitespace char, or to column 0 when
+    // already there.
+    fn move_first_non_whitespace(&mut self) {
+        let indent = self.buffer.line(self.cursor.row).map_or(0, |line| {
+            line.chars().take_while(|ch| ch.is_whitespace()).count()
+        });
+        self.cursor.col = if self.cursor.col == indent { 0 } else { indent };
+    }
+
+    fn move_buffer_start(&mut self) {
+        self.cursor = Cursor::new(0, 0);
+    }
+
+    fn move_buffer_end(&mut self) {
+        self.cursor = self.buffer.end_position();
+    }
+
+    // Char column of line `row` under display column `goal`, or the line end.
+    fn col_at_display(&self, row: usize, goal: usize) -> usize {
+        let len = self.buffer.line_len_chars(row);
+        self.col_under(row, (0, len), 0, goal, true)
+    }
+
+    // Moves `count` visual rows up or down, aiming for the screen column the
+    // cursor had before the first of a run of visual moves.
+    fn move_visual(&mut self, count: usize, down: bool) {
+        let (sub_row, col) = self.visual_pos(self.cursor);
+        let goal = self.take_goal_col(true, col);
+        let from = (self.cursor.row, sub_row);
+        let (row, sub_row) = if down {
+            self.visual_row_forward(from, count)
+        } else {
+            self.visual_row_back(from, count)
+        };
+        if (row, sub_row) != from {
+            self.cursor = Cursor::new(row, self.col_at_screen(row, sub_row, goal));
         }
+        self.keep_goal_col(true, goal);
+    }
 
-        self.cursor.row += 1;
-        let line_len = self.buffer.line_len_chars(self.cursor.row);
-        if self.cursor.col > line_len {
-            self.cursor.col = line_len;
+    fn move_visual_up(&mut self) {
+        self.move_visual(1, false);
+    }
+
+    fn move_visual_down(&mut self) {
+        self.move_visual(1, true);
+    }
+
+    fn move_visual_line_start(&mut self) {
+        let (sub_row, _) = self.visual_pos(self.cursor);
+        self.cursor.col = self.wrap_rows(self.cursor.row)[sub_row].0;
+    }
+
+    fn move_visual_line_end(&mut self) {
+        let (sub_row, _) = self.visual_pos(self.cursor);
+        self.cursor.col = self.col_at_screen(self.cursor.row, sub_row, usize::MAX);
+    }
+
+    // Scrolls the viewport by a page or half page and moves every cursor by
+    // the same number of visual rows, so the primary cursor keeps its screen
+    // row unless the scroll stops at either end of the buffer.
+    fn scroll_page(&mut self, action: Action) {
+        let height = self.viewport.height;
+        let (count, down) = match action {
+            Action::PageUp => (height, false),
+            Action::PageDown => (height, true),
+            Action::HalfPageUp => (height / 2, false),
+            _ => (height / 2, true),
+        };
+        let count = count.max(1);
+
+        let top = (self.viewport.row_offset, self.viewport.wrap_offset);
+        let new_top = if down {
+            let last_row = self.buffer.line_count().saturating_sub(1);
+            let last = (last_row, self.wrap_rows(last_row).len() - 1);
+            let max_top = self.visual_row_back(last, height.saturating_sub(1));
+            self.visual_row_forward(top, count).min(max_top).max(top)
+        } else {
+            self.visual_row_back(top, count)
+        };
+        (self.viewport.row_offset, self.viewport.wrap_offset) = new_top;
+
+        self.for_each_cursor(|editor| {
+            editor.anchor = None;
+            editor.move_visual(count, down);
+            (false, None)
+        });
+    }
+
+    fn insert_text(&mut self, at: Cursor, text: &str) -> Cursor {
+        let end = self.buffer.insert(at, text);
+        for selection in &mut self.others {
+            *selection = selection.map(|position| position.shifted_by_insert(at, end));
+        }
+        self.history.record(Edit::Insert {
+            at,
+            text: text.to_string(),
+        });
+        end
+    }
+
+    fn remove_text(&mut self, start: Cursor, end: Cursor) -> String {
+        let removed = self.buffer.remove(start, end);
+        for selection in &mut self.others {
+            *selection = selection.map(|position| position.shifted_by_remove(start, end));
+        }
+        if !removed.is_empty() {
+            self.history.record(Edit::Delete {
+                at: start,
+                text: removed.clone(),
+            });
         }
+        removed
     }
 
     fn insert_char(&mut self, ch: char) -> bool {
         let row = self.cursor.row;
-        let col = self.cursor.col;
-        let line = match self.buffer.lines.get_mut(row) {
-            Some(value) => value,
-            None => return false,
-        };
-        let line_len = line.chars().count();
-        let col = col.min(line_len);
-        let byte_idx = char_to_byte_index(line, col);
-
-        line.insert(byte_idx, ch);
+        if row >= self.buffer.line_count() {
+            return false;
+        }
 
-        self.cursor.col = col + 1;
+        let col = self.cursor.col.min(self.buffer.line_len_chars(row));
+        let mut encoded = [0; 4];
+        self.cursor = self.insert_text(Cursor::new(row, col), ch.encode_utf8(&mut encoded));
 
         true
     }
 
     fn insert_newline(&mut self) -> bool {
         let row = self.cursor.row;
-        let col = self.cursor.col;
-        let line = match self.buffer.lines.get_mut(row) {
-            Some(value) => value,
-            None => return false,
-        };
-        let line_len = line.chars().count();
-        let col = col.min(line_len);
-        let byte_idx = char_to_byte_index(line, col);
-        let right = line.split_off(byte_idx);
+        if row >= self.buffer.line_count() {
+            return false;
+        }
 
-        self.buffer.lines.insert(row + 1, right);
-        self.cursor.row = row + 1;
-        self.cursor.col = 0;
+        let col = self.cursor.col.min(self.buffer.line_len_chars(row));
+        self.cursor = self.insert_text(Cursor::new(row, col), "\n");
 
         true
     }
@@ -402,24 +2123,13 @@ impl Editor {
         }
 
         if col > 0 {
-            let line = match self.buffer.lines.get_mut(row) {
-                Some(value) => value,
-                None => return false,
-            };
-
-            let line_len = line.chars().count();
-            let col = col.min(line_len);
-
+            let col = col.min(self.buffer.line_len_chars(row));
             if col == 0 {
                 return false;
             }
 
-            let remove_col = col - 1;
-            let start = char_to_byte_index(line, remove_col);
-            let end = char_to_byte_index(line, remove_col + 1);
-
-            line.replace_range(start..end, "");
-
+            let remove_col = self.prev_col(row, col);
+            self.remove_text(Cursor::new(row, remove_col), Cursor::new(row, col));
             self.cursor.col = remove_col;
 
             return true;
@@ -428,14 +2138,10 @@ impl Editor {
         if row > 0 {
             let prev_row = row - 1;
             let prev_len = self.buffer.line_len_chars(prev_row);
-            let current_line = self.buffer.lines.remove(row);
-
-            if let Some(prev_line) = self.buffer.lines.get_mut(prev_row) {
-                prev_line.push_str(&current_line);
-                self.cursor.row = prev_row;
-                self.cursor.col = prev_len;
-                return true;
-            }
+            self.remove_text(Cursor::new(prev_row, prev_len), Cursor::new(row, 0));
+            self.cursor.row = prev_row;
+            self.cursor.col = prev_len;
+            return true;
         }
 
         false
@@ -451,24 +2157,48 @@ impl Editor {
 
         let line_len = self.buffer.line_len_chars(row);
         if col < line_len {
-            let line = match self.buffer.lines.get_mut(row) {
-                Some(value) => value,
-                None => return false,
-            };
-            let start = char_to_byte_index(line, col);
-            let end = char_to_byte_index(line, col + 1);
-            line.replace_range(start..end, "");
+            let end_col = self.next_col(row, col);
+            self.remove_text(Cursor::new(row, col), Cursor::new(row, end_col));
             return true;
         }
 
         if col == line_len && row + 1 < self.buffer.line_count() {
-            let next_line = self.buffer.lines.remove(row + 1);
-            if let Some(line) = self.buffer.lines.get_mut(row) {
-                line.push_str(&next_line);
-                return true;
-            }
+            self.remove_text(Cursor::new(row, col), Cursor::new(row + 1, 0));
+            return true;
         }
 
         false
     }
 }
+
+impl Editor<Buffer> {
+    // File-format settings are not undoable edits, but they mark the editor
+    // dirty when they change what gets saved.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.change_format(|buffer| buffer.set_encoding(encoding));
+    }
+
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.change_format(|buffer| buffer.set_line_ending(line_ending));
+    }
+
+    pub fn set_bom(&mut self, bom: bool) {
+        self.change_format(|buffer| buffer.set_bom(bom));
+    }
+
+    pub fn set_final_newline(&mut self, final_newline: bool) {
+        self.change_format(|buffer| buffer.set_final_newline(final_newline));
+    }
+
+    pub fn set_ensure_final_newline(&mut self, ensure: bool) {
+        self.change_format(|buffer| buffer.set_ensure_final_newline(ensure));
+    }
+
+    fn change_format(&mut self, change: impl FnOnce(&mut Buffer)) {
+        let before = self.buffer.format_key();
+        change(&mut self.buffer);
+        if self.buffer.format_key() != before {
+            self.dirty = true;
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+
+use crate::text::{escape_byte, escaped_byte};
+use crate::types::Cursor;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    // UTF-8 that keeps invalid bytes as escape units and writes them back.
+    Utf8Lossless,
+    Latin1,
+    Windows1252,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    // Picks an encoding for raw file bytes: a UTF-8 or UTF-16 BOM wins, then
+    // UTF-16 is guessed when at least 40% of the units have a zero byte on
+    // one side and few on the other (ASCII-heavy UTF-16 text), then UTF-8
+    // if the bytes are valid, and Windows-1252 otherwise.
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        if bytes.starts_with(&[0xef, 0xbb, 0xbf]) {
+            return Encoding::Utf8;
+        }
+        if bytes.starts_with(&[0xff, 0xfe]) {
+            return Encoding::Utf16Le;
+        }
+        if bytes.starts_with(&[0xfe, 0xff]) {
+            return Encoding::Utf16Be;
+        }
+
+        let sample = &bytes[..bytes.len().min(4096) & !1];
+        let units = sample.len() / 2;
+        if units > 0 {
+            let even_zeros = sample.iter().step_by(2).filter(|&&byte| byte == 0).count();
+            let odd_zeros = sample[1..]
+                .iter()
+                .step_by(2)
+                .filter(|&&byte| byte == 0)
+                .count();
+            if odd_zeros * 10 >= units * 4 && even_zeros * 4 < odd_zeros {
+                return Encoding::Utf16Le;
+            }
+            if even_zeros * 10 >= units * 4 && odd_zeros * 4 < even_zeros {
+                return Encoding::Utf16Be;
+            }
+        }
+
+        if std::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else {
+            Encoding::Windows1252
+        }
+    }
+
+    // Only these encodings write the buffer's BOM.
+    pub(crate) fn defines_bom(self) -> bool {
+        matches!(
+            self,
+            Encoding::Utf8 | Encoding::Utf8Lossless | Encoding::Utf16Le | Encoding::Utf16Be
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EncodingError {
+    // The bytes are not valid in the encoding; `offset` is the first bad byte.
+    Invalid { offset: usize },
+    // Buffer chars the target encoding cannot represent, with their positions.
+    Unrepresentable(Vec<(Cursor, char)>),
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingError::Invalid { offset } => {
+                write!(f, "invalid byte sequence at offset {offset}")
+            }
+            EncodingError::Unrepresentable(chars) => {
+                write!(
+                    f,
+                    "{} character(s) cannot be represented in the target encoding",
+                    chars.len()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+// Windows-1252 code points for bytes 0x80..=0x9f. The five bytes the code page
+// leaves undefined map to the matching C1 control so they round-trip.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+pub(crate) fn decode(bytes: &[u8], encoding: Encoding) -> Result<String, EncodingError> {
+    match encoding {
+        Encoding::Utf8 => std::str::from_utf8(bytes)
+            .map(str::to_string)
+            .map_err(|err| EncodingError::Invalid {
+                offset: err.valid_up_to(),
+            }),
+        Encoding::Utf8Lossless => Ok(decode_lossless(bytes)),
+        Encoding::Latin1 => Ok(bytes.iter().map(|&byte| char::from(byte)).collect()),
+        Encoding::Windows1252 => Ok(bytes
+            .iter()
+            .map(|&byte| match byte {
+                0x80..=0x9f => WINDOWS_1252_HIGH[usize::from(byte - 0x80)],
+                _ => char::from(byte),
+            })
+            .collect()),
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let units = bytes.chunks_exact(2).map(|pair| {
+                let pair = [pair[0], pair[1]];
+                if encoding == Encoding::Utf16Le {
+                    u16::from_le_bytes(pair)
+                } else {
+                    u16::from_be_bytes(pair)
+                }
+            });
+            let mut text = String::with_capacity(bytes.len() / 2);
+            let mut offset = 0;
+            for decoded in char::decode_utf16(units) {
+                let ch = decoded.map_err(|_| EncodingError::Invalid { offset })?;
+                offset += 2 * ch.len_utf16();
+                text.push(ch);
+            }
+            if bytes.len() % 2 == 1 {
+                return Err(EncodingError::Invalid {
+                    offset: bytes.len() - 1,
+                });
+            }
+            Ok(text)
+        }
+    }
+}
+
+// Valid UTF-8 is kept as text, except chars that would read back as escape
+// units, which are escaped byte by byte like invalid sequences.
+fn decode_lossless(mut bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    while !bytes.is_empty() {
+        let (valid, invalid) = match std::str::from_utf8(bytes) {
+            Ok(valid) => (valid, 0),
+            Err(err) => {
+                let valid = std::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap_or_default();
+                let invalid = err.error_len().unwrap_or(bytes.len() - err.valid_up_to());
+                (valid, invalid)
+            }
+        };
+        for ch in valid.chars() {
+            if escaped_byte(ch).is_some() {
+                let mut buf = [0; 4];
+                text.extend(ch.encode_utf8(&mut buf).bytes().map(escape_byte));
+            } else {
+                text.push(ch);
+            }
+        }
+        let rest = &bytes[valid.len()..];
+        text.extend(rest[..invalid].iter().copied().map(escape_byte));
+        bytes = &rest[invalid..];
+    }
+    text
+}
+
+// Appends `ch` in `encoding`; returns false if it has no representation.
+pub(crate) fn encode_char(ch: char, encoding: Encoding, out: &mut Vec<u8>) -> bool {
+    match encoding {
+        Encoding::Utf8 => {
+            let mut buf = [0; 4];
+            out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+        }
+        Encoding::Utf8Lossless => match escaped_byte(ch) {
+            Some(byte) => out.push(byte),
+            None => return encode_char(ch, Encoding::Utf8, out),
+        },
+        Encoding::Latin1 => match u8::try_from(u32::from(ch)) {
+            Ok(byte) => out.push(byte),
+            Err(_) => return false,
+        },
+        Encoding::Windows1252 => {
+            let byte = match u8::try_from(u32::from(ch)) {
+                Ok(byte) if !(0x80..=0x9f).contains(&byte) => Some(byte),
+                _ => WINDOWS_1252_HIGH
+                    .iter()
+                    .position(|&high| high == ch)
+                    .map(|idx| 0x80 + idx as u8),
+            };
+            match byte {
+                Some(byte) => out.push(byte),
+                None => return false,
+            }
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let mut buf = [0; 2];
+            for unit in ch.encode_utf16(&mut buf) {
+                if encoding == Encoding::Utf16Le {
+                    out.extend_from_slice(&unit.to_le_bytes());
+                } else {
+                    out.extend_from_slice(&unit.to_be_bytes());
+                }
+            }
+        }
+    }
+    true
+}
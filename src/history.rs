@@ -0,0 +1,793 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::storage::TextStorage;
+use crate::types::Cursor;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Edit {
+    Insert { at: Cursor, text: String },
+    Delete { at: Cursor, text: String },
+}
+
+impl Edit {
+    fn inverse(&self) -> Edit {
+        match self {
+            Edit::Insert { at, text } => Edit::Delete {
+                at: *at,
+                text: text.clone(),
+            },
+            Edit::Delete { at, text } => Edit::Insert {
+                at: *at,
+                text: text.clone(),
+            },
+        }
+    }
+
+    // Folds `next` into `self` when it continues the same run of typing or
+    // deleting, so a coalesced step stays a short list of edits.
+    fn merge(&mut self, next: &Edit) -> bool {
+        match (self, next) {
+            (
+                Edit::Insert { at, text },
+                Edit::Insert {
+                    at: next_at,
+                    text: next_text,
+                },
+            ) if text_end(*at, text) == *next_at => {
+                text.push_str(next_text);
+                true
+            }
+            (
+                Edit::Delete { at, text },
+                Edit::Delete {
+                    at: next_at,
+                    text: next_text,
+                },
+            ) if *at == *next_at => {
+                text.push_str(next_text);
+                true
+            }
+            (
+                Edit::Delete { at, text },
+                Edit::Delete {
+                    at: next_at,
+                    text: next_text,
+                },
+            ) if text_end(*next_at, next_text) == *at => {
+                text.insert_str(0, next_text);
+                *at = *next_at;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn apply<S: TextStorage + ?Sized>(&self, buffer: &mut S) {
+        match self {
+            Edit::Insert { at, text } => {
+                buffer.insert(*at, text);
+            }
+            Edit::Delete { at, text } => {
+                buffer.remove(*at, text_end(*at, text));
+            }
+        }
+    }
+}
+
+// Position reached after inserting `text` at `start`.
+pub(crate) fn text_end(start: Cursor, text: &str) -> Cursor {
+    match text.rfind('\n') {
+        Some(idx) => Cursor::new(
+            start.row + text.matches('\n').count(),
+            text[idx + 1..].chars().count(),
+        ),
+        None => Cursor::new(start.row, start.col + text.chars().count()),
+    }
+}
+
+const HISTORY_MAGIC: &str = "edit_core-history";
+const HISTORY_VERSION: u32 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HistoryError {
+    UnsupportedVersion(u32),
+    TextMismatch,
+    Malformed(String),
+    ReadOnly,
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::UnsupportedVersion(version) => {
+                write!(f, "unsupported history version {version}")
+            }
+            HistoryError::TextMismatch => {
+                write!(f, "history does not match the buffer text")
+            }
+            HistoryError::Malformed(reason) => write!(f, "malformed history: {reason}"),
+            HistoryError::ReadOnly => write!(f, "storage is read-only"),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+// 64-bit FNV-1a, used to tie saved history to the text it applies to.
+pub(crate) fn text_hash(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+// Kinds of edits that coalesce with an immediately preceding edit of the
+// same kind into a single undo step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EditKind {
+    Insert,
+    DeleteBackward,
+    DeleteForward,
+}
+
+// One recorded state in the undo tree. Node 0 is the root (the text as it
+// was when the editor was created); every other node holds the edits that
+// turn its parent's text into its own, and every cursor (primary first) from
+// before and after them. Nodes are numbered in creation order, so a node's
+// index doubles as its chronological state number.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Node {
+    parent: usize,
+    children: Vec<usize>,
+    redo_child: Option<usize>,
+    depth: usize,
+    edits: Vec<Edit>,
+    before: Vec<Cursor>,
+    after: Vec<Cursor>,
+    time: SystemTime,
+}
+
+impl Node {
+    fn root() -> Self {
+        Self {
+            parent: 0,
+            children: Vec::new(),
+            redo_child: None,
+            depth: 0,
+            edits: Vec::new(),
+            before: vec![Cursor::new(0, 0)],
+            after: vec![Cursor::new(0, 0)],
+            time: SystemTime::now(),
+        }
+    }
+
+    fn push(&mut self, edit: Edit) {
+        if let Some(last) = self.edits.last_mut()
+            && last.merge(&edit)
+        {
+            return;
+        }
+        self.edits.push(edit);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Transaction {
+    depth: usize,
+    before: Vec<Cursor>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct History {
+    nodes: Vec<Node>,
+    current: usize,
+    pending: Vec<Edit>,
+    last_kind: Option<EditKind>,
+    transaction: Option<Transaction>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node::root()],
+            current: 0,
+            pending: Vec::new(),
+            last_kind: None,
+            transaction: None,
+        }
+    }
+}
+
+impl History {
+    pub(crate) fn record(&mut self, edit: Edit) {
+        self.pending.push(edit);
+    }
+
+    // Turns the edits recorded since the last commit into a new node below the
+    // current one, or extends the current node when `kind` continues its run.
+    // Inside a transaction the edits stay pending until the outermost
+    // `end_transaction`.
+    pub(crate) fn commit(
+        &mut self,
+        before: Vec<Cursor>,
+        after: Vec<Cursor>,
+        kind: Option<EditKind>,
+    ) {
+        if self.pending.is_empty() || self.transaction.is_some() {
+            return;
+        }
+
+        let edits = std::mem::take(&mut self.pending);
+        let continues_run = kind.is_some() && kind == self.last_kind && self.current != 0;
+        let id = self.nodes.len();
+        let node = &mut self.nodes[self.current];
+        if continues_run && node.children.is_empty() && node.after == before {
+            for edit in edits {
+                node.push(edit);
+            }
+            node.after = after;
+            node.time = SystemTime::now();
+        } else {
+            node.children.push(id);
+            node.redo_child = Some(id);
+            let mut node = Node {
+                parent: self.current,
+                children: Vec::new(),
+                redo_child: None,
+                depth: node.depth + 1,
+                edits: Vec::with_capacity(edits.len()),
+                before,
+                after,
+                time: SystemTime::now(),
+            };
+            for edit in edits {
+                node.push(edit);
+            }
+            self.nodes.push(node);
+            self.current = id;
+        }
+
+        self.last_kind = kind;
+    }
+
+    // Stops the next edit from coalescing with the previous step.
+    pub(crate) fn break_run(&mut self) {
+        self.last_kind = None;
+    }
+
+    pub(crate) fn begin_transaction(&mut self, cursors: Vec<Cursor>) {
+        match &mut self.transaction {
+            Some(transaction) => transaction.depth += 1,
+            None => {
+                self.transaction = Some(Transaction {
+                    depth: 1,
+                    before: cursors,
+                });
+            }
+        }
+    }
+
+    pub(crate) fn end_transaction(&mut self, cursors: Vec<Cursor>) {
+        let Some(transaction) = &mut self.transaction else {
+            return;
+        };
+
+        transaction.depth -= 1;
+        if transaction.depth == 0 {
+            self.finish_transaction(cursors);
+        }
+    }
+
+    // Closes any open transaction, committing everything recorded inside it.
+    pub(crate) fn finish_transaction(&mut self, cursors: Vec<Cursor>) {
+        let Some(transaction) = self.transaction.take() else {
+            return;
+        };
+
+        self.last_kind = None;
+        self.commit(transaction.before, cursors, None);
+    }
+
+    pub(crate) fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        self.current != 0
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        self.nodes[self.current].redo_child.is_some()
+    }
+
+    pub(crate) fn current_state(&self) -> usize {
+        self.current
+    }
+
+    pub(crate) fn state_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub(crate) fn state_time(&self, state: usize) -> Option<SystemTime> {
+        self.nodes.get(state).map(|node| node.time)
+    }
+
+    pub(crate) fn redo_branch_count(&self) -> usize {
+        self.nodes[self.current].children.len()
+    }
+
+    pub(crate) fn select_redo_branch(&mut self, index: usize) -> bool {
+        let node = &mut self.nodes[self.current];
+        match node.children.get(index) {
+            Some(&child) => {
+                node.redo_child = Some(child);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn undo<S: TextStorage + ?Sized>(&mut self, buffer: &mut S) -> Option<Vec<Cursor>> {
+        if self.current == 0 {
+            return None;
+        }
+
+        self.last_kind = None;
+        Some(self.step_up(buffer))
+    }
+
+    pub(crate) fn redo<S: TextStorage + ?Sized>(&mut self, buffer: &mut S) -> Option<Vec<Cursor>> {
+        let child = self.nodes[self.current].redo_child?;
+        self.last_kind = None;
+        Some(self.step_down(child, buffer))
+    }
+
+    // Moves to any recorded state, undoing up to the common ancestor and then
+    // redoing down the target's branch.
+    pub(crate) fn goto<S: TextStorage + ?Sized>(
+        &mut self,
+        target: usize,
+        buffer: &mut S,
+    ) -> Option<Vec<Cursor>> {
+        if target >= self.nodes.len() || target == self.current {
+            return None;
+        }
+
+        self.last_kind = None;
+
+        let mut down = Vec::new();
+        let mut ancestor = target;
+        while self.nodes[ancestor].depth > self.nodes[self.current].depth {
+            down.push(ancestor);
+            ancestor = self.nodes[ancestor].parent;
+        }
+
+        let mut cursors = self.nodes[self.current].after.clone();
+        while self.nodes[self.current].depth > self.nodes[ancestor].depth {
+            cursors = self.step_up(buffer);
+        }
+        while self.current != ancestor {
+            cursors = self.step_up(buffer);
+            down.push(ancestor);
+            ancestor = self.nodes[ancestor].parent;
+        }
+
+        for &node in down.iter().rev() {
+            cursors = self.step_down(node, buffer);
+        }
+        Some(cursors)
+    }
+
+    // Latest state, in recording order, whose time is at or before `time`;
+    // the root when none is. Node numbers give the order because the clock
+    // can be set back between edits, so times need not increase.
+    pub(crate) fn state_at(&self, time: SystemTime) -> usize {
+        self.nodes
+            .iter()
+            .rposition(|node| node.time <= time)
+            .unwrap_or(0)
+    }
+
+    fn step_up<S: TextStorage + ?Sized>(&mut self, buffer: &mut S) -> Vec<Cursor> {
+        let node = &self.nodes[self.current];
+        for edit in node.edits.iter().rev() {
+            edit.inverse().apply(buffer);
+        }
+        let cursors = node.before.clone();
+        let id = self.current;
+        self.current = node.parent;
+        self.nodes[self.current].redo_child = Some(id);
+        cursors
+    }
+
+    fn step_down<S: TextStorage + ?Sized>(&mut self, child: usize, buffer: &mut S) -> Vec<Cursor> {
+        let node = &self.nodes[child];
+        for edit in &node.edits {
+            edit.apply(buffer);
+        }
+        let cursors = node.after.clone();
+        self.nodes[self.current].redo_child = Some(child);
+        self.current = child;
+        cursors
+    }
+
+    // The node `finish_transaction` would add for the edits of an open
+    // transaction, so they can be saved without closing it.
+    fn pending_node(&self, cursors: &[Cursor]) -> Option<Node> {
+        let transaction = self.transaction.as_ref()?;
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let mut node = Node {
+            parent: self.current,
+            children: Vec::new(),
+            redo_child: None,
+            depth: self.nodes[self.current].depth + 1,
+            edits: Vec::with_capacity(self.pending.len()),
+            before: transaction.before.clone(),
+            after: cursors.to_vec(),
+            time: SystemTime::now(),
+        };
+        for edit in &self.pending {
+            node.push(edit.clone());
+        }
+        Some(node)
+    }
+
+    // Serializes the tree for the given current text, with the edits of an
+    // open transaction saved as the current node. The format is a small
+    // line-oriented header followed by one record per node; edit texts are
+    // length-prefixed so they may contain any character, including '\n'.
+    pub(crate) fn encode(&self, text: &str, cursors: &[Cursor]) -> Vec<u8> {
+        let pending = self.pending_node(cursors);
+        let (current, count) = match pending {
+            Some(_) => (self.nodes.len(), self.nodes.len() + 1),
+            None => (self.current, self.nodes.len()),
+        };
+
+        let mut out = Vec::new();
+        out.extend_from_slice(format!("{HISTORY_MAGIC} {HISTORY_VERSION}\n").as_bytes());
+        out.extend_from_slice(format!("hash {:016x}\n", text_hash(text)).as_bytes());
+        out.extend_from_slice(format!("current {current}\n").as_bytes());
+        out.extend_from_slice(format!("nodes {count}\n").as_bytes());
+
+        for (id, node) in self.nodes.iter().chain(&pending).enumerate() {
+            let redo_child = if pending.is_some() && id == self.current {
+                Some(current)
+            } else {
+                node.redo_child
+            };
+            let redo_child = match redo_child {
+                Some(child) => child.to_string(),
+                None => "-".to_string(),
+            };
+            let since_epoch = node.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            out.extend_from_slice(
+                format!(
+                    "node {} {} {} {} {}\n",
+                    node.parent,
+                    redo_child,
+                    since_epoch.as_secs(),
+                    since_epoch.subsec_nanos(),
+                    node.edits.len(),
+                )
+                .as_bytes(),
+            );
+            encode_cursors(&mut out, "before", &node.before);
+            encode_cursors(&mut out, "after", &node.after);
+
+            for edit in &node.edits {
+                let (tag, at, text) = match edit {
+                    Edit::Insert { at, text } => ('i', at, text),
+                    Edit::Delete { at, text } => ('d', at, text),
+                };
+                out.extend_from_slice(
+                    format!("{tag} {} {} {}\n", at.row, at.col, text.len()).as_bytes(),
+                );
+                out.extend_from_slice(text.as_bytes());
+                out.push(b'\n');
+            }
+        }
+
+        out
+    }
+
+    // Rebuilds a tree saved by `encode`, refusing it unless it was saved for
+    // exactly `text`.
+    pub(crate) fn decode(data: &[u8], text: &str) -> Result<History, HistoryError> {
+        let mut reader = Reader { data, pos: 0 };
+
+        let mut header = reader.fields()?;
+        if header.next() != Some(HISTORY_MAGIC) {
+            return Err(malformed("missing history header"));
+        }
+        let version: u32 = parse_field(header.next(), "version")?;
+        // Version 1 stored only the primary cursor of each node.
+        if version != 1 && version != HISTORY_VERSION {
+            return Err(HistoryError::UnsupportedVersion(version));
+        }
+
+        let hash = reader.keyed("hash")?;
+        let hash = u64::from_str_radix(hash, 16).map_err(|_| malformed("invalid hash"))?;
+        if hash != text_hash(text) {
+            return Err(HistoryError::TextMismatch);
+        }
+
+        let current: usize = parse_field(Some(reader.keyed("current")?), "current")?;
+        let count: usize = parse_field(Some(reader.keyed("nodes")?), "nodes")?;
+        if count == 0 || current >= count {
+            return Err(malformed("invalid node count"));
+        }
+
+        // `count` is untrusted, so nodes are only allocated as they are read.
+        let mut nodes: Vec<Node> = Vec::new();
+        for id in 0..count {
+            let mut fields = reader.fields()?;
+            if fields.next() != Some("node") {
+                return Err(malformed("expected node record"));
+            }
+            let parent: usize = parse_field(fields.next(), "parent")?;
+            let redo_child = match fields.next() {
+                Some("-") => None,
+                field => Some(parse_field::<usize>(field, "redo child")?),
+            };
+            let secs: u64 = parse_field(fields.next(), "time")?;
+            let nanos: u32 = parse_field(fields.next(), "time")?;
+            let (before, after, edit_count) = if version == 1 {
+                let before = vec![parse_cursor(&mut fields)?];
+                let after = vec![parse_cursor(&mut fields)?];
+                (before, after, parse_field(fields.next(), "edit count")?)
+            } else {
+                let edit_count = parse_field(fields.next(), "edit count")?;
+                (
+                    reader.cursors("before")?,
+                    reader.cursors("after")?,
+                    edit_count,
+                )
+            };
+
+            if id > 0 && parent >= id {
+                return Err(malformed("node parent must precede the node"));
+            }
+            if id == 0 && edit_count > 0 {
+                return Err(malformed("root node has edits"));
+            }
+
+            let mut edits = Vec::new();
+            for _ in 0..edit_count {
+                let mut fields = reader.fields()?;
+                let tag = fields.next();
+                let at = Cursor::new(
+                    parse_field(fields.next(), "edit position")?,
+                    parse_field(fields.next(), "edit position")?,
+                );
+                let len: usize = parse_field(fields.next(), "edit length")?;
+                let text = reader.text(len)?;
+                edits.push(match tag {
+                    Some("i") => Edit::Insert { at, text },
+                    Some("d") => Edit::Delete { at, text },
+                    _ => return Err(malformed("unknown edit kind")),
+                });
+            }
+
+            let depth = if id == 0 { 0 } else { nodes[parent].depth + 1 };
+            if id > 0 {
+                nodes[parent].children.push(id);
+            }
+            nodes.push(Node {
+                parent: if id == 0 { 0 } else { parent },
+                children: Vec::new(),
+                redo_child,
+                depth,
+                edits,
+                before,
+                after,
+                time: UNIX_EPOCH + Duration::new(secs, nanos),
+            });
+        }
+
+        for node in &nodes {
+            if let Some(child) = node.redo_child
+                && !node.children.contains(&child)
+            {
+                return Err(malformed("redo child is not a child of its node"));
+            }
+        }
+        replay_edits(&nodes, current, text)?;
+
+        Ok(History {
+            nodes,
+            current,
+            pending: Vec::new(),
+            last_kind: None,
+            transaction: None,
+        })
+    }
+}
+
+// Replays every edit in the tree, starting from `text` at node `current`,
+// so that undo and redo never apply an edit that does not fit the text in
+// front of it: an insert must land inside the text and a delete must remove
+// exactly the text it recorded.
+fn replay_edits(nodes: &[Node], current: usize, text: &str) -> Result<(), HistoryError> {
+    let mut replay = Replay {
+        lines: text.split('\n').map(str::to_string).collect(),
+    };
+    let mut id = current;
+    while id != 0 {
+        for edit in nodes[id].edits.iter().rev() {
+            replay.apply(&edit.inverse())?;
+        }
+        id = nodes[id].parent;
+    }
+
+    // Depth-first from the root: each node's edits are applied on the way
+    // down and reverted on the way back up.
+    let mut stack = vec![(0, 0)];
+    while let Some((id, next)) = stack.pop() {
+        if let Some(&child) = nodes[id].children.get(next) {
+            stack.push((id, next + 1));
+            for edit in &nodes[child].edits {
+                replay.apply(edit)?;
+            }
+            stack.push((child, 0));
+        } else if id != 0 {
+            for edit in nodes[id].edits.iter().rev() {
+                replay.apply(&edit.inverse())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Plain lines that saved edits are checked against while decoding.
+struct Replay {
+    lines: Vec<String>,
+}
+
+impl Replay {
+    fn contains(&self, at: Cursor) -> bool {
+        at.row < self.lines.len() && at.col <= self.line_len_chars(at.row)
+    }
+
+    fn apply(&mut self, edit: &Edit) -> Result<(), HistoryError> {
+        let fits = match edit {
+            Edit::Insert { at, .. } => self.contains(*at),
+            Edit::Delete { at, text } => {
+                let end = text_end(*at, text);
+                self.contains(*at) && self.contains(end) && self.text_range(*at, end) == *text
+            }
+        };
+        if !fits {
+            return Err(malformed("edit does not fit the text"));
+        }
+
+        edit.apply(self);
+        Ok(())
+    }
+}
+
+impl TextStorage for Replay {
+    fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn line(&self, row: usize) -> Option<&str> {
+        self.lines.get(row).map(String::as_str)
+    }
+
+    fn insert(&mut self, at: Cursor, text: &str) -> Cursor {
+        let byte = self.char_to_byte(at.row, at.col);
+        let tail = self.lines[at.row].split_off(byte);
+        let mut pieces = text.split('\n');
+        self.lines[at.row].push_str(pieces.next().unwrap_or_default());
+        let mut row = at.row;
+        for piece in pieces {
+            row += 1;
+            self.lines.insert(row, piece.to_string());
+        }
+        let col = self.line_len_chars(row);
+        self.lines[row].push_str(&tail);
+        Cursor::new(row, col)
+    }
+
+    fn remove(&mut self, start: Cursor, end: Cursor) -> String {
+        let removed = self.text_range(start, end);
+        let end_byte = self.char_to_byte(end.row, end.col);
+        let tail = self.lines[end.row].split_off(end_byte);
+        let start_byte = self.char_to_byte(start.row, start.col);
+        self.lines.drain(start.row + 1..=end.row);
+        self.lines[start.row].truncate(start_byte);
+        self.lines[start.row].push_str(&tail);
+        removed
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn line(&mut self) -> Result<&'a str, HistoryError> {
+        let rest = &self.data[self.pos..];
+        let Some(len) = rest.iter().position(|&byte| byte == b'\n') else {
+            return Err(malformed("unexpected end of data"));
+        };
+        self.pos += len + 1;
+        std::str::from_utf8(&rest[..len]).map_err(|_| malformed("invalid UTF-8"))
+    }
+
+    fn fields(&mut self) -> Result<std::str::Split<'a, char>, HistoryError> {
+        Ok(self.line()?.split(' '))
+    }
+
+    fn keyed(&mut self, key: &str) -> Result<&'a str, HistoryError> {
+        let line = self.line()?;
+        match line.split_once(' ') {
+            Some((found, value)) if found == key => Ok(value),
+            _ => Err(malformed(&format!("expected {key}"))),
+        }
+    }
+
+    // A `key` line holding a cursor count and that many cursors.
+    fn cursors(&mut self, key: &str) -> Result<Vec<Cursor>, HistoryError> {
+        let mut fields = self.keyed(key)?.split(' ');
+        let count: usize = parse_field(fields.next(), "cursor count")?;
+        let mut cursors = Vec::new();
+        while cursors.len() < count {
+            cursors.push(parse_cursor(&mut fields)?);
+        }
+        if count == 0 || fields.next().is_some() {
+            return Err(malformed("invalid cursors"));
+        }
+        Ok(cursors)
+    }
+
+    fn text(&mut self, len: usize) -> Result<String, HistoryError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end < self.data.len());
+        let Some(end) = end else {
+            return Err(malformed("unexpected end of data"));
+        };
+        if self.data[end] != b'\n' {
+            return Err(malformed("edit text length mismatch"));
+        }
+        let text = std::str::from_utf8(&self.data[self.pos..end])
+            .map_err(|_| malformed("invalid UTF-8"))?;
+        self.pos = end + 1;
+        Ok(text.to_string())
+    }
+}
+
+fn parse_field<T: std::str::FromStr>(field: Option<&str>, name: &str) -> Result<T, HistoryError> {
+    field
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| malformed(&format!("invalid {name}")))
+}
+
+fn parse_cursor<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Result<Cursor, HistoryError> {
+    Ok(Cursor::new(
+        parse_field(fields.next(), "cursor")?,
+        parse_field(fields.next(), "cursor")?,
+    ))
+}
+
+fn encode_cursors(out: &mut Vec<u8>, key: &str, cursors: &[Cursor]) {
+    let mut line = format!("{key} {}", cursors.len());
+    for cursor in cursors {
+        line.push_str(&format!(" {} {}", cursor.row, cursor.col));
+    }
+    line.push('\n');
+    out.extend_from_slice(line.as_bytes());
+}
+
+fn malformed(reason: &str) -> HistoryError {
+    HistoryError::Malformed(reason.to_string())
+}
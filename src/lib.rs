@@ -1,8 +1,19 @@
 mod buffer;
 mod editor;
+mod encoding;
+mod history;
+mod registers;
+mod rope;
+mod storage;
 mod text;
 mod types;
 
 pub use crate::buffer::Buffer;
 pub use crate::editor::Editor;
-pub use crate::types::{Action, Cursor, Viewport};
+pub use crate::encoding::{Encoding, EncodingError};
+pub use crate::history::HistoryError;
+pub use crate::registers::{Clipboard, RegisterContent, RegisterKind};
+pub use crate::storage::TextStorage;
+pub use crate::types::{
+    Action, BlockSelection, Cursor, LineEnding, Selection, Viewport, VisualRow,
+};
@@ -0,0 +1,232 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::text::normalize_line_endings;
+
+pub(crate) const UNNAMED_REGISTER: char = '"';
+pub(crate) const YANK_REGISTER: char = '0';
+pub(crate) const DELETE_REGISTER: char = '-';
+pub(crate) const CLIPBOARD_REGISTER: char = '+';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterKind {
+    Charwise,
+    Linewise,
+    Blockwise,
+}
+
+// Register text uses '\n' between lines. Linewise content ends with '\n';
+// blockwise content holds one block row per line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegisterContent {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+impl RegisterContent {
+    pub fn new(text: impl Into<String>, kind: RegisterKind) -> Self {
+        Self {
+            text: text.into(),
+            kind,
+        }
+    }
+
+    pub fn charwise(text: impl Into<String>) -> Self {
+        Self::new(text, RegisterKind::Charwise)
+    }
+
+    pub fn linewise(text: impl Into<String>) -> Self {
+        Self::new(text, RegisterKind::Linewise)
+    }
+
+    pub fn blockwise(rows: &[String]) -> Self {
+        Self::new(rows.join("\n"), RegisterKind::Blockwise)
+    }
+
+    fn append(&mut self, other: RegisterContent) {
+        if self.kind == RegisterKind::Linewise && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+        self.text.push_str(&other.text);
+        if other.kind == RegisterKind::Linewise {
+            self.kind = RegisterKind::Linewise;
+        }
+    }
+}
+
+// Host hook for the system clipboard, exposed as the `+` register.
+pub trait Clipboard {
+    fn get(&mut self) -> Option<String>;
+    fn set(&mut self, text: &str);
+}
+
+// Clones of an editor share its clipboard.
+pub(crate) type SharedClipboard = Arc<Mutex<dyn Clipboard + Send>>;
+
+#[derive(Clone, Default)]
+pub(crate) struct Registers {
+    unnamed: Option<RegisterContent>,
+    named: [Option<RegisterContent>; 26],
+    yank: Option<RegisterContent>,
+    delete: Option<RegisterContent>,
+    clipboard: Option<SharedClipboard>,
+}
+
+impl Registers {
+    pub(crate) fn set_clipboard(&mut self, clipboard: Option<SharedClipboard>) {
+        self.clipboard = clipboard;
+    }
+
+    pub(crate) fn is_valid(name: char) -> bool {
+        matches!(
+            name,
+            UNNAMED_REGISTER | YANK_REGISTER | DELETE_REGISTER | CLIPBOARD_REGISTER
+        ) || name.is_ascii_alphabetic()
+    }
+
+    pub(crate) fn get(&self, name: char) -> Option<RegisterContent> {
+        match name {
+            UNNAMED_REGISTER => self.unnamed.clone(),
+            YANK_REGISTER => self.yank.clone(),
+            DELETE_REGISTER => self.delete.clone(),
+            CLIPBOARD_REGISTER => {
+                // Host text may use "\r\n" or '\r' between lines.
+                let clipboard = self.clipboard.as_ref()?;
+                let text = clipboard
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .get()?;
+                let text = normalize_line_endings(&text).into_owned();
+                let kind = if text.ends_with('\n') {
+                    RegisterKind::Linewise
+                } else {
+                    RegisterKind::Charwise
+                };
+                Some(RegisterContent::new(text, kind))
+            }
+            _ => self.named.get(named_index(name)?)?.clone(),
+        }
+    }
+
+    // Stores `content` under `name`. Uppercase letters append to the
+    // matching lowercase register.
+    pub(crate) fn set(&mut self, name: char, content: RegisterContent) {
+        match name {
+            UNNAMED_REGISTER => self.unnamed = Some(content),
+            YANK_REGISTER => self.yank = Some(content),
+            DELETE_REGISTER => self.delete = Some(content),
+            CLIPBOARD_REGISTER => {
+                if let Some(clipboard) = &self.clipboard {
+                    clipboard
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .set(&content.text);
+                }
+            }
+            _ => {
+                let Some(index) = named_index(name) else {
+                    return;
+                };
+                let slot = &mut self.named[index];
+                match slot {
+                    Some(existing) if name.is_ascii_uppercase() => existing.append(content),
+                    _ => *slot = Some(content),
+                }
+            }
+        }
+    }
+
+    // Records a copy or cut: the target register, the unnamed register, and
+    // either the last-yank or the last-delete register.
+    pub(crate) fn record(&mut self, target: Option<char>, content: RegisterContent, deleted: bool) {
+        if let Some(name) = target.filter(|&name| name != UNNAMED_REGISTER) {
+            self.set(name, content.clone());
+        }
+        let history_register = if deleted {
+            DELETE_REGISTER
+        } else {
+            YANK_REGISTER
+        };
+        self.set(history_register, content.clone());
+
+        let unnamed = match target {
+            Some(name) if name.is_ascii_uppercase() => self.get(name).unwrap_or(content),
+            _ => content,
+        };
+        self.set(UNNAMED_REGISTER, unnamed);
+    }
+}
+
+fn named_index(name: char) -> Option<usize> {
+    name.is_ascii_alphabetic()
+        .then(|| usize::from(name.to_ascii_lowercase() as u8 - b'a'))
+}
+
+impl fmt::Debug for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registers")
+            .field("unnamed", &self.unnamed)
+            .field("named", &self.named)
+            .field("yank", &self.yank)
+            .field("delete", &self.delete)
+            .field("clipboard", &self.clipboard.is_some())
+            .finish()
+    }
+}
+
+impl PartialEq for Registers {
+    fn eq(&self, other: &Self) -> bool {
+        let same_clipboard = match (&self.clipboard, &other.clipboard) {
+            (Some(left), Some(right)) => Arc::ptr_eq(left, right),
+            (None, None) => true,
+            _ => false,
+        };
+        same_clipboard
+            && self.unnamed == other.unnamed
+            && self.named == other.named
+            && self.yank == other.yank
+            && self.delete == other.delete
+    }
+}
+
+impl Eq for Registers {}
+
+const KILL_RING_CAPACITY: usize = 60;
+
+// Emacs-style kill ring. Index 0 is the most recent kill.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct KillRing {
+    entries: Vec<String>,
+}
+
+impl KillRing {
+    pub(crate) fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub(crate) fn push(&mut self, text: String) {
+        self.entries.insert(0, text);
+        self.entries.truncate(KILL_RING_CAPACITY);
+    }
+
+    // Adds `text` to the most recent kill, in front of it for backward kills.
+    pub(crate) fn extend(&mut self, text: &str, backward: bool) {
+        match self.entries.first_mut() {
+            Some(entry) if backward => entry.insert_str(0, text),
+            Some(entry) => entry.push_str(text),
+            None => self.push(text.to_string()),
+        }
+    }
+
+    // Entry `index` places back from the most recent, wrapping around.
+    pub(crate) fn get(&self, index: usize) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        Some(&self.entries[index % self.entries.len()])
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
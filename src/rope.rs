@@ -0,0 +1,967 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::text::{LineInfo, char_to_byte_index, char_width};
+
+// A rope of lines: an implicit treap whose in-order traversal is the line
+// sequence. Every node caches the number of lines and chars in its subtree,
+// so finding a line by row, splicing lines in or out, and converting between
+// absolute char offsets and (row, col) positions are all O(log n). A line of
+// more than `LONG_LINE` bytes is itself a treap of chunks, so inserting,
+// removing, and indexing chars inside it are O(log n) in its length too.
+
+// Lines longer than this are split into chunks.
+const LONG_LINE: usize = 4096;
+// Chunks are cut to this size, leaving room to type into them, and chunked
+// lines that shrink to it become one string again.
+const CHUNK: usize = 1024;
+// A chunk that grows past this is cut again.
+const MAX_CHUNK: usize = 2048;
+
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// What `merge` and `split` need from a node of either treap.
+trait TreapNode: Sized {
+    fn priority(&self) -> u64;
+    fn size(&self) -> usize;
+    fn left(&mut self) -> &mut Option<Box<Self>>;
+    fn right(&mut self) -> &mut Option<Box<Self>>;
+    fn update(&mut self);
+}
+
+fn size<T: TreapNode>(link: &Option<Box<T>>) -> usize {
+    link.as_ref().map_or(0, |node| node.size())
+}
+
+fn merge<T: TreapNode>(left: Option<Box<T>>, right: Option<Box<T>>) -> Option<Box<T>> {
+    match (left, right) {
+        (None, right) => right,
+        (left, None) => left,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority() >= right.priority() {
+                let child = left.right().take();
+                *left.right() = merge(child, Some(right));
+                left.update();
+                Some(left)
+            } else {
+                let child = right.left().take();
+                *right.left() = merge(Some(left), child);
+                right.update();
+                Some(right)
+            }
+        }
+    }
+}
+
+// Splits off the first `count` nodes.
+fn split<T: TreapNode>(link: Option<Box<T>>, count: usize) -> (Option<Box<T>>, Option<Box<T>>) {
+    let Some(mut node) = link else {
+        return (None, None);
+    };
+
+    let left_size = size(node.left());
+    if count <= left_size {
+        let (first, rest) = split(node.left().take(), count);
+        *node.left() = rest;
+        node.update();
+        (first, Some(node))
+    } else {
+        let (first, rest) = split(node.right().take(), count - left_size - 1);
+        *node.right() = first;
+        node.update();
+        (Some(node), rest)
+    }
+}
+
+// xorshift64*; deterministic so buffers behave the same run to run.
+fn next_priority(seed: &mut u64) -> u64 {
+    *seed ^= *seed >> 12;
+    *seed ^= *seed << 25;
+    *seed ^= *seed >> 27;
+    seed.wrapping_mul(0x2545_f491_4f6c_dd1d)
+}
+
+type ChunkLink = Option<Box<Chunk>>;
+
+#[derive(Clone)]
+struct Chunk {
+    text: String,
+    info: LineInfo,
+    // Display width of the text's chars other than tabs, and its tabs.
+    text_width: usize,
+    text_tabs: usize,
+    priority: u64,
+    // Subtree totals. `plain_prefix` counts the leading chars that are
+    // printable ASCII, like `LineInfo::plain_prefix`.
+    size: usize,
+    chars: usize,
+    bytes: usize,
+    width: usize,
+    tabs: usize,
+    plain_prefix: usize,
+    left: ChunkLink,
+    right: ChunkLink,
+}
+
+impl Chunk {
+    fn new(text: String, priority: u64) -> Box<Chunk> {
+        let info = LineInfo::new(&text);
+        let (text_width, text_tabs) = untabbed_width(&text);
+        let mut chunk = Box::new(Chunk {
+            text,
+            info,
+            text_width,
+            text_tabs,
+            priority,
+            size: 0,
+            chars: 0,
+            bytes: 0,
+            width: 0,
+            tabs: 0,
+            plain_prefix: 0,
+            left: None,
+            right: None,
+        });
+        chunk.update();
+        chunk
+    }
+}
+
+impl TreapNode for Chunk {
+    fn priority(&self) -> u64 {
+        self.priority
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn left(&mut self) -> &mut ChunkLink {
+        &mut self.left
+    }
+
+    fn right(&mut self) -> &mut ChunkLink {
+        &mut self.right
+    }
+
+    fn update(&mut self) {
+        let left_chars = chunk_chars(&self.left);
+        let left_plain = chunk_plain_prefix(&self.left);
+        self.size = 1 + size(&self.left) + size(&self.right);
+        self.chars = left_chars + self.info.chars() + chunk_chars(&self.right);
+        self.bytes = chunk_bytes(&self.left) + self.text.len() + chunk_bytes(&self.right);
+        self.width = chunk_width(&self.left) + self.text_width + chunk_width(&self.right);
+        self.tabs = chunk_tabs(&self.left) + self.text_tabs + chunk_tabs(&self.right);
+        self.plain_prefix = if left_plain < left_chars {
+            left_plain
+        } else if self.info.plain_prefix() < self.info.chars() {
+            left_chars + self.info.plain_prefix()
+        } else {
+            left_chars + self.info.chars() + chunk_plain_prefix(&self.right)
+        };
+    }
+}
+
+fn chunk_chars(link: &ChunkLink) -> usize {
+    link.as_ref().map_or(0, |chunk| chunk.chars)
+}
+
+fn chunk_bytes(link: &ChunkLink) -> usize {
+    link.as_ref().map_or(0, |chunk| chunk.bytes)
+}
+
+fn chunk_width(link: &ChunkLink) -> usize {
+    link.as_ref().map_or(0, |chunk| chunk.width)
+}
+
+fn chunk_tabs(link: &ChunkLink) -> usize {
+    link.as_ref().map_or(0, |chunk| chunk.tabs)
+}
+
+fn chunk_plain_prefix(link: &ChunkLink) -> usize {
+    link.as_ref().map_or(0, |chunk| chunk.plain_prefix)
+}
+
+// Display width of the chars of `text` other than tabs, and how many tabs
+// there are.
+fn untabbed_width(text: &str) -> (usize, usize) {
+    text.chars().fold((0, 0), |(width, tabs), ch| match ch {
+        '\t' => (width, tabs + 1),
+        _ => (width + char_width(ch), tabs),
+    })
+}
+
+// `untabbed_width` of the first `col` chars under `link`.
+fn chunk_prefix_width(mut link: &ChunkLink, mut col: usize) -> (usize, usize) {
+    let (mut width, mut tabs) = (0, 0);
+    while let Some(chunk) = link {
+        let left_chars = chunk_chars(&chunk.left);
+        if col < left_chars {
+            link = &chunk.left;
+            continue;
+        }
+
+        col -= left_chars;
+        width += chunk_width(&chunk.left);
+        tabs += chunk_tabs(&chunk.left);
+        if col <= chunk.info.chars() {
+            let end = chunk.info.char_to_byte(&chunk.text, col);
+            let (part_width, part_tabs) = untabbed_width(&chunk.text[..end]);
+            return (width + part_width, tabs + part_tabs);
+        }
+        col -= chunk.info.chars();
+        width += chunk.text_width;
+        tabs += chunk.text_tabs;
+        link = &chunk.right;
+    }
+    (width, tabs)
+}
+
+// Appends chars `start..end` of the chunks under `link` to `out`.
+fn push_chunk_range(link: &ChunkLink, start: usize, end: usize, out: &mut String) {
+    let Some(chunk) = link else {
+        return;
+    };
+    if start >= end {
+        return;
+    }
+
+    let left_chars = chunk_chars(&chunk.left);
+    let own_end = left_chars + chunk.info.chars();
+    if start < left_chars {
+        push_chunk_range(&chunk.left, start, end.min(left_chars), out);
+    }
+    if start < own_end && end > left_chars {
+        let from = chunk
+            .info
+            .char_to_byte(&chunk.text, start.saturating_sub(left_chars));
+        let to = chunk
+            .info
+            .char_to_byte(&chunk.text, end.min(own_end) - left_chars);
+        out.push_str(&chunk.text[from..to]);
+    }
+    if end > own_end {
+        let start = start.saturating_sub(own_end);
+        push_chunk_range(&chunk.right, start, end - own_end, out);
+    }
+}
+
+// Chunks holding `text`: one if it fits in `MAX_CHUNK` bytes, else pieces
+// of at most `CHUNK` bytes cut at char boundaries.
+fn chunks_of(text: String, seed: &mut u64) -> ChunkLink {
+    if text.is_empty() {
+        return None;
+    }
+    if text.len() <= MAX_CHUNK {
+        return Some(Chunk::new(text, next_priority(seed)));
+    }
+
+    let mut link = None;
+    let mut rest = text.as_str();
+    while !rest.is_empty() {
+        let mut end = CHUNK.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (piece, tail) = rest.split_at(end);
+        link = merge(
+            link,
+            Some(Chunk::new(piece.to_string(), next_priority(seed))),
+        );
+        rest = tail;
+    }
+    link
+}
+
+fn push_chunks(link: &ChunkLink, out: &mut String) {
+    if let Some(chunk) = link {
+        push_chunks(&chunk.left, out);
+        out.push_str(&chunk.text);
+        push_chunks(&chunk.right, out);
+    }
+}
+
+// Index and first char of the chunk holding char `col`; past the end, of
+// the last chunk.
+fn find_chunk(mut link: &ChunkLink, mut col: usize) -> (usize, usize) {
+    let mut index = 0;
+    let mut start = 0;
+    while let Some(chunk) = link {
+        let left_chars = chunk_chars(&chunk.left);
+        if col < left_chars {
+            link = &chunk.left;
+            continue;
+        }
+
+        index += size(&chunk.left);
+        start += left_chars;
+        col -= left_chars;
+        if col < chunk.info.chars() || chunk.right.is_none() {
+            break;
+        }
+        index += 1;
+        start += chunk.info.chars();
+        col -= chunk.info.chars();
+        link = &chunk.right;
+    }
+    (index, start)
+}
+
+// The text of one line: a single string, or chunks once it is long. A
+// chunked line joins its chunks the first time it is read as a `&str` after
+// a change.
+#[derive(Clone)]
+enum LineText {
+    Flat {
+        text: String,
+        info: LineInfo,
+    },
+    Chunked {
+        root: ChunkLink,
+        seed: u64,
+        joined: OnceLock<String>,
+    },
+}
+
+impl LineText {
+    fn new(text: String) -> Self {
+        let mut line = LineText::Flat {
+            info: LineInfo::new(&text),
+            text,
+        };
+        line.rebalance();
+        line
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            LineText::Flat { text, .. } => text,
+            LineText::Chunked { root, joined, .. } => joined.get_or_init(|| {
+                let mut text = String::with_capacity(chunk_bytes(root));
+                push_chunks(root, &mut text);
+                text
+            }),
+        }
+    }
+
+    fn into_string(self) -> String {
+        match self {
+            LineText::Flat { text, .. } => text,
+            LineText::Chunked { root, joined, .. } => joined.into_inner().unwrap_or_else(|| {
+                let mut text = String::with_capacity(chunk_bytes(&root));
+                push_chunks(&root, &mut text);
+                text
+            }),
+        }
+    }
+
+    fn chars(&self) -> usize {
+        match self {
+            LineText::Flat { info, .. } => info.chars(),
+            LineText::Chunked { root, .. } => chunk_chars(root),
+        }
+    }
+
+    fn plain_prefix(&self) -> usize {
+        match self {
+            LineText::Flat { info, .. } => info.plain_prefix(),
+            LineText::Chunked { root, .. } => chunk_plain_prefix(root),
+        }
+    }
+
+    // Chars `start..end`, clamped to the line.
+    fn slice(&self, start: usize, end: usize) -> String {
+        let end = end.min(self.chars());
+        match self {
+            LineText::Flat { text, info } => {
+                let start_byte = info.char_to_byte(text, start);
+                let end_byte = info.char_to_byte(text, end).max(start_byte);
+                text[start_byte..end_byte].to_string()
+            }
+            LineText::Chunked { root, .. } => {
+                let mut text = String::new();
+                push_chunk_range(root, start, end, &mut text);
+                text
+            }
+        }
+    }
+
+    // Display width of chars `start..end`, or `None` if one of them is a
+    // tab.
+    fn untabbed_width(&self, start: usize, end: usize) -> Option<usize> {
+        let end = end.min(self.chars()).max(start);
+        let (width, tabs) = match self {
+            LineText::Flat { text, info } => {
+                let start_byte = info.char_to_byte(text, start);
+                untabbed_width(&text[start_byte..info.char_to_byte(text, end)])
+            }
+            LineText::Chunked { root, .. } => {
+                let (start_width, start_tabs) = chunk_prefix_width(root, start);
+                let (end_width, end_tabs) = chunk_prefix_width(root, end);
+                (end_width - start_width, end_tabs - start_tabs)
+            }
+        };
+        (tabs == 0).then_some(width)
+    }
+
+    // Byte index of char `col`, clamped to the line's length.
+    fn char_to_byte(&self, mut col: usize) -> usize {
+        let mut link = match self {
+            LineText::Flat { text, info } => return info.char_to_byte(text, col),
+            LineText::Chunked { root, .. } => root,
+        };
+        let mut byte = 0;
+        while let Some(chunk) = link {
+            let left_chars = chunk_chars(&chunk.left);
+            if col < left_chars {
+                link = &chunk.left;
+                continue;
+            }
+
+            col -= left_chars;
+            byte += chunk_bytes(&chunk.left);
+            if col <= chunk.info.chars() {
+                return byte + chunk.info.char_to_byte(&chunk.text, col);
+            }
+            col -= chunk.info.chars();
+            byte += chunk.text.len();
+            link = &chunk.right;
+        }
+        byte
+    }
+
+    // Char column of byte index `byte` (a char boundary).
+    fn byte_to_char(&self, mut byte: usize) -> usize {
+        let mut link = match self {
+            LineText::Flat { text, info } => return info.byte_to_char(text, byte),
+            LineText::Chunked { root, .. } => root,
+        };
+        let mut col = 0;
+        while let Some(chunk) = link {
+            let left_bytes = chunk_bytes(&chunk.left);
+            if byte < left_bytes {
+                link = &chunk.left;
+                continue;
+            }
+
+            byte -= left_bytes;
+            col += chunk_chars(&chunk.left);
+            if byte <= chunk.text.len() {
+                return col + chunk.info.byte_to_char(&chunk.text, byte);
+            }
+            byte -= chunk.text.len();
+            col += chunk.info.chars();
+            link = &chunk.right;
+        }
+        col
+    }
+
+    fn insert(&mut self, col: usize, inserted: &str) {
+        if inserted.is_empty() {
+            return;
+        }
+
+        match self {
+            LineText::Flat { text, info } => {
+                text.insert_str(info.char_to_byte(text, col), inserted);
+                info.refresh(text, col);
+            }
+            LineText::Chunked { root, seed, joined } => {
+                joined.take();
+                let (index, start) = find_chunk(root, col);
+                let (before, rest) = split(root.take(), index);
+                let (chunk, after) = split(rest, 1);
+                let mut text = String::new();
+                if let Some(chunk) = chunk {
+                    text = chunk.text;
+                    let byte = char_to_byte_index(&text, col - start);
+                    text.insert_str(byte, inserted);
+                } else {
+                    text.push_str(inserted);
+                }
+                *root = merge(merge(before, chunks_of(text, seed)), after);
+            }
+        }
+        self.rebalance();
+    }
+
+    // Removes chars `start..end`, clamped to the line, and returns them.
+    fn remove(&mut self, start: usize, end: usize) -> String {
+        let end = end.min(self.chars());
+        if start >= end {
+            return String::new();
+        }
+
+        let removed = match self {
+            LineText::Flat { text, info } => {
+                let start_byte = info.char_to_byte(text, start);
+                let end_byte = info.char_to_byte(text, end);
+                let removed = text.drain(start_byte..end_byte).collect();
+                info.refresh(text, start);
+                removed
+            }
+            LineText::Chunked { root, seed, joined } => {
+                joined.take();
+                let (first, first_start) = find_chunk(root, start);
+                let (last, _) = find_chunk(root, end - 1);
+                let (before, rest) = split(root.take(), first);
+                let (middle, after) = split(rest, last - first + 1);
+                let mut text = String::new();
+                push_chunks(&middle, &mut text);
+                let start_byte = char_to_byte_index(&text, start - first_start);
+                let end_byte = start_byte + char_to_byte_index(&text[start_byte..], end - start);
+                let removed = text[start_byte..end_byte].to_string();
+                text.replace_range(start_byte..end_byte, "");
+                *root = merge(merge(before, chunks_of(text, seed)), after);
+                removed
+            }
+        };
+        self.rebalance();
+        removed
+    }
+
+    // Cuts the line at char `col` and returns the part after it.
+    fn split_off(&mut self, col: usize) -> LineText {
+        let tail = match self {
+            LineText::Flat { text, info } => {
+                let tail = text.split_off(info.char_to_byte(text, col));
+                info.refresh(text, col);
+                LineText::new(tail)
+            }
+            LineText::Chunked { root, seed, joined } => {
+                joined.take();
+                let (index, start) = find_chunk(root, col);
+                let (before, rest) = split(root.take(), index);
+                let (chunk, after) = split(rest, 1);
+                let (head, tail) = match chunk {
+                    Some(chunk) => {
+                        let mut head = chunk.text;
+                        let tail = head.split_off(char_to_byte_index(&head, col - start));
+                        (head, tail)
+                    }
+                    None => (String::new(), String::new()),
+                };
+                *root = merge(before, chunks_of(head, seed));
+                let mut tail_seed = next_priority(seed);
+                let mut tail = LineText::Chunked {
+                    root: merge(chunks_of(tail, &mut tail_seed), after),
+                    seed: tail_seed,
+                    joined: OnceLock::new(),
+                };
+                tail.rebalance();
+                tail
+            }
+        };
+        self.rebalance();
+        tail
+    }
+
+    fn append(&mut self, other: LineText) {
+        if let (LineText::Flat { text, info }, LineText::Flat { text: other, .. }) =
+            (&mut *self, &other)
+        {
+            let col = info.chars();
+            text.push_str(other);
+            info.refresh(text, col);
+        } else {
+            let mut seed = match self {
+                LineText::Chunked { seed, .. } => *seed,
+                LineText::Flat { .. } => SEED,
+            };
+            let left = self.take_chunks(&mut seed);
+            let mut other = other;
+            let right = other.take_chunks(&mut seed);
+            *self = LineText::Chunked {
+                root: merge(left, right),
+                seed,
+                joined: OnceLock::new(),
+            };
+        }
+        self.rebalance();
+    }
+
+    fn take_chunks(&mut self, seed: &mut u64) -> ChunkLink {
+        match std::mem::replace(self, LineText::new(String::new())) {
+            LineText::Flat { text, .. } => chunks_of(text, seed),
+            LineText::Chunked { root, .. } => root,
+        }
+    }
+
+    // Chunks a line that grew past `LONG_LINE` bytes and joins one that
+    // shrank to `CHUNK` bytes.
+    fn rebalance(&mut self) {
+        match self {
+            LineText::Flat { text, .. } if text.len() > LONG_LINE => {
+                let mut seed = SEED;
+                let root = chunks_of(std::mem::take(text), &mut seed);
+                *self = LineText::Chunked {
+                    root,
+                    seed,
+                    joined: OnceLock::new(),
+                };
+            }
+            LineText::Chunked { root, .. } if chunk_bytes(root) <= CHUNK => {
+                let mut text = String::new();
+                push_chunks(root, &mut text);
+                *self = LineText::Flat {
+                    info: LineInfo::new(&text),
+                    text,
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+type Link = Option<Box<Node>>;
+
+#[derive(Clone)]
+struct Node {
+    line: LineText,
+    priority: u64,
+    size: usize,
+    chars: usize,
+    left: Link,
+    right: Link,
+}
+
+impl Node {
+    fn new(line: LineText, priority: u64) -> Box<Node> {
+        let chars = line.chars();
+        Box::new(Node {
+            line,
+            priority,
+            size: 1,
+            chars,
+            left: None,
+            right: None,
+        })
+    }
+}
+
+impl TreapNode for Node {
+    fn priority(&self) -> u64 {
+        self.priority
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn left(&mut self) -> &mut Link {
+        &mut self.left
+    }
+
+    fn right(&mut self) -> &mut Link {
+        &mut self.right
+    }
+
+    fn update(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+        self.chars = self.line.chars() + chars(&self.left) + chars(&self.right);
+    }
+}
+
+fn chars(link: &Link) -> usize {
+    link.as_ref().map_or(0, |node| node.chars)
+}
+
+// Builds a perfectly balanced subtree. Priorities shrink with depth so the
+// result is a valid treap that later random-priority inserts keep balanced.
+fn build(lines: &mut impl Iterator<Item = String>, count: usize, depth: u32) -> Link {
+    if count == 0 {
+        return None;
+    }
+
+    let left_count = count / 2;
+    let left = build(lines, left_count, depth + 1);
+    let line = lines.next().unwrap_or_default();
+    let mut node = Node::new(LineText::new(line), u64::MAX >> depth.min(63));
+    node.left = left;
+    node.right = build(lines, count - left_count - 1, depth + 1);
+    node.update();
+    Some(node)
+}
+
+fn into_lines(link: Link, out: &mut Vec<String>) {
+    if let Some(node) = link {
+        let node = *node;
+        into_lines(node.left, out);
+        out.push(node.line.into_string());
+        into_lines(node.right, out);
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct LineRope {
+    root: Link,
+    seed: u64,
+}
+
+impl LineRope {
+    pub(crate) fn from_lines(lines: Vec<String>) -> Self {
+        let count = lines.len();
+        Self {
+            root: build(&mut lines.into_iter(), count, 0),
+            seed: SEED,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub(crate) fn total_chars(&self) -> usize {
+        chars(&self.root)
+    }
+
+    pub(crate) fn get(&self, row: usize) -> Option<&str> {
+        self.node(row).map(|node| node.line.as_str())
+    }
+
+    pub(crate) fn line_chars(&self, row: usize) -> usize {
+        self.node(row).map_or(0, |node| node.line.chars())
+    }
+
+    pub(crate) fn char_to_byte(&self, row: usize, col: usize) -> usize {
+        self.node(row).map_or(0, |node| node.line.char_to_byte(col))
+    }
+
+    pub(crate) fn byte_to_char(&self, row: usize, byte: usize) -> usize {
+        self.node(row)
+            .map_or(0, |node| node.line.byte_to_char(byte))
+    }
+
+    pub(crate) fn plain_prefix(&self, row: usize) -> usize {
+        self.node(row).map_or(0, |node| node.line.plain_prefix())
+    }
+
+    pub(crate) fn slice(&self, row: usize, start: usize, end: usize) -> String {
+        self.node(row)
+            .map_or_else(String::new, |node| node.line.slice(start, end))
+    }
+
+    pub(crate) fn untabbed_width(&self, row: usize, start: usize, end: usize) -> Option<usize> {
+        self.node(row)
+            .map_or(Some(0), |node| node.line.untabbed_width(start, end))
+    }
+
+    fn node(&self, mut row: usize) -> Option<&Node> {
+        let mut link = &self.root;
+        while let Some(node) = link {
+            let left_size = size(&node.left);
+            if row < left_size {
+                link = &node.left;
+            } else if row == left_size {
+                return Some(node);
+            } else {
+                row -= left_size + 1;
+                link = &node.right;
+            }
+        }
+        None
+    }
+
+    // Inserts `text` at char `col` of line `row`.
+    pub(crate) fn insert(&mut self, row: usize, col: usize, text: &str) {
+        self.edit(row, |line| line.insert(col, text));
+    }
+
+    // Removes chars `start..end` of line `row`, clamped to the line, and
+    // returns them.
+    pub(crate) fn remove(&mut self, row: usize, start: usize, end: usize) -> String {
+        self.edit(row, |line| line.remove(start, end))
+            .unwrap_or_default()
+    }
+
+    // Moves the part of line `row` after char `col` to a new line below it.
+    pub(crate) fn split_line(&mut self, row: usize, col: usize) {
+        let Some(tail) = self.edit(row, |line| line.split_off(col)) else {
+            return;
+        };
+        let priority = self.next_priority();
+        let (first, rest) = split(self.root.take(), row + 1);
+        self.root = merge(merge(first, Some(Node::new(tail, priority))), rest);
+    }
+
+    // Appends line `row + 1` to line `row`.
+    pub(crate) fn join_lines(&mut self, row: usize) {
+        let (first, rest) = split(self.root.take(), row + 1);
+        let (next, rest) = split(rest, 1);
+        self.root = merge(first, rest);
+        if let Some(next) = next {
+            self.edit(row, |line| line.append(next.line));
+        }
+    }
+
+    // Runs `edit` on one line, then refreshes the cached counts on the path
+    // back to the root.
+    fn edit<R>(&mut self, row: usize, edit: impl FnOnce(&mut LineText) -> R) -> Option<R> {
+        fn visit<R>(
+            link: &mut Link,
+            row: usize,
+            edit: impl FnOnce(&mut LineText) -> R,
+        ) -> Option<R> {
+            let node = link.as_mut()?;
+            let left_size = size(&node.left);
+            let result = if row < left_size {
+                visit(&mut node.left, row, edit)
+            } else if row == left_size {
+                Some(edit(&mut node.line))
+            } else {
+                visit(&mut node.right, row - left_size - 1, edit)
+            };
+            node.update();
+            result
+        }
+
+        visit(&mut self.root, row, edit)
+    }
+
+    // Inserts `lines` so that the first of them ends up at `row`.
+    pub(crate) fn insert_lines(&mut self, row: usize, lines: Vec<String>) {
+        if lines.is_empty() {
+            return;
+        }
+
+        let (first, rest) = split(self.root.take(), row);
+        let middle = if lines.len() == 1 {
+            let priority = self.next_priority();
+            lines
+                .into_iter()
+                .next()
+                .map(|line| Node::new(LineText::new(line), priority))
+        } else {
+            self.random_subtree(lines)
+        };
+        self.root = merge(merge(first, middle), rest);
+    }
+
+    // Removes rows `start..end` and returns them in order.
+    pub(crate) fn remove_lines(&mut self, start: usize, end: usize) -> Vec<String> {
+        if start >= end {
+            return Vec::new();
+        }
+
+        let (first, rest) = split(self.root.take(), start);
+        let (middle, rest) = split(rest, end - start);
+        self.root = merge(first, rest);
+
+        let mut removed = Vec::with_capacity(end - start);
+        into_lines(middle, &mut removed);
+        removed
+    }
+
+    pub(crate) fn iter_from(&self, row: usize) -> Iter<'_> {
+        let mut stack = Vec::new();
+        let mut link = &self.root;
+        let mut row = row;
+        while let Some(node) = link {
+            let left_size = size(&node.left);
+            if row < left_size {
+                stack.push(node.as_ref());
+                link = &node.left;
+            } else if row == left_size {
+                stack.push(node.as_ref());
+                break;
+            } else {
+                row -= left_size + 1;
+                link = &node.right;
+            }
+        }
+        Iter { stack }
+    }
+
+    pub(crate) fn iter(&self) -> Iter<'_> {
+        self.iter_from(0)
+    }
+
+    // Absolute char offset of a position, counting one char per line break.
+    pub(crate) fn char_offset(&self, mut row: usize, col: usize) -> usize {
+        let mut offset = 0;
+        let mut link = &self.root;
+        while let Some(node) = link {
+            let left_size = size(&node.left);
+            if row < left_size {
+                link = &node.left;
+            } else {
+                offset += chars(&node.left) + left_size;
+                if row == left_size {
+                    return offset + col.min(node.line.chars());
+                }
+                offset += node.line.chars() + 1;
+                row -= left_size + 1;
+                link = &node.right;
+            }
+        }
+        offset.saturating_sub(1)
+    }
+
+    // Inverse of `char_offset`; offsets past the end clamp to the last line.
+    pub(crate) fn position_at(&self, mut offset: usize) -> (usize, usize) {
+        let mut row = 0;
+        let mut link = &self.root;
+        while let Some(node) = link {
+            let left_total = chars(&node.left) + size(&node.left);
+            if offset < left_total {
+                link = &node.left;
+                continue;
+            }
+
+            offset -= left_total;
+            row += size(&node.left);
+            if offset <= node.line.chars() || node.right.is_none() {
+                return (row, offset.min(node.line.chars()));
+            }
+            offset -= node.line.chars() + 1;
+            row += 1;
+            link = &node.right;
+        }
+        (row.saturating_sub(1), 0)
+    }
+
+    fn random_subtree(&mut self, lines: Vec<String>) -> Link {
+        let mut link = None;
+        for line in lines {
+            let priority = self.next_priority();
+            link = merge(link, Some(Node::new(LineText::new(line), priority)));
+        }
+        link
+    }
+
+    fn next_priority(&mut self) -> u64 {
+        next_priority(&mut self.seed)
+    }
+}
+
+pub(crate) struct Iter<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let node = self.stack.pop()?;
+        let mut link = &node.right;
+        while let Some(child) = link {
+            self.stack.push(child.as_ref());
+            link = &child.left;
+        }
+        Some(node.line.as_str())
+    }
+}
+
+impl fmt::Debug for LineRope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl PartialEq for LineRope {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for LineRope {}
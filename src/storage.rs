@@ -0,0 +1,154 @@
+use crate::text::{char_to_byte_index, char_width};
+use crate::types::Cursor;
+
+// The text backend an `Editor` edits. Rows and columns are char based like
+// `Cursor`, lines never contain '\n', and a storage always holds at least one
+// (possibly empty) line. `insert` and `remove` receive positions the editor
+// has already clamped, with `start <= end`.
+pub trait TextStorage {
+    fn line_count(&self) -> usize;
+
+    fn line(&self, row: usize) -> Option<&str>;
+
+    // Inserts `text` (which may contain '\n') and returns the position just
+    // past it.
+    fn insert(&mut self, at: Cursor, text: &str) -> Cursor;
+
+    // Removes the text between two positions and returns it, using '\n' as
+    // the line separator.
+    fn remove(&mut self, start: Cursor, end: Cursor) -> String;
+
+    // A read-only storage never sees `insert`/`remove`, not even from undo or
+    // redo; the editor still moves, selects, and copies.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    fn line_len_chars(&self, row: usize) -> usize {
+        self.line(row).map_or(0, |line| line.chars().count())
+    }
+
+    // Byte index of char column `col` in line `row`, clamped to the line.
+    fn char_to_byte(&self, row: usize, col: usize) -> usize {
+        self.line(row)
+            .map_or(0, |line| char_to_byte_index(line, col))
+    }
+
+    // Char column of byte index `byte` in line `row`, clamped to the line.
+    // `byte` must lie on a char boundary.
+    fn byte_to_char(&self, row: usize, byte: usize) -> usize {
+        self.line(row)
+            .map_or(0, |line| line[..byte.min(line.len())].chars().count())
+    }
+
+    // Leading chars of line `row` that are printable ASCII, so their display
+    // columns equal their char columns.
+    fn plain_prefix_chars(&self, row: usize) -> usize {
+        self.line(row).map_or(0, |line| {
+            line.chars()
+                .take_while(|ch| (' '..='~').contains(ch))
+                .count()
+        })
+    }
+
+    // Display width of chars `start..end` of line `row`, or `None` if one of
+    // them is a tab, whose width depends on where it starts.
+    fn untabbed_width(&self, row: usize, start: usize, end: usize) -> Option<usize> {
+        let text = self.text_range(Cursor::new(row, start), Cursor::new(row, end));
+        (!text.contains('\t')).then(|| text.chars().map(char_width).sum())
+    }
+
+    fn end_position(&self) -> Cursor {
+        let last_row = self.line_count().saturating_sub(1);
+        Cursor::new(last_row, self.line_len_chars(last_row))
+    }
+
+    // Text between two positions (`start <= end`), using '\n' as the line
+    // separator.
+    fn text_range(&self, start: Cursor, end: Cursor) -> String {
+        if start.row >= self.line_count() || end.row >= self.line_count() || start >= end {
+            return String::new();
+        }
+
+        let mut text = String::new();
+        for row in start.row..=end.row {
+            let line = self.line(row).unwrap_or_default();
+            let from = if row == start.row {
+                self.char_to_byte(row, start.col)
+            } else {
+                text.push('\n');
+                0
+            };
+            let to = if row == end.row {
+                self.char_to_byte(row, end.col).max(from)
+            } else {
+                line.len()
+            };
+            text.push_str(&line[from..to]);
+        }
+        text
+    }
+
+    // The whole text with '\n' between lines.
+    fn text(&self) -> String {
+        self.text_range(Cursor::new(0, 0), self.end_position())
+    }
+}
+
+// Start of the first occurrence of `needle` at or after `from`, wrapping
+// around to the start of the storage. Lines are searched one at a time, so
+// the whole text is never built.
+pub(crate) fn find_next<S: TextStorage + ?Sized>(
+    storage: &S,
+    needle: &str,
+    from: Cursor,
+) -> Option<Cursor> {
+    if needle.is_empty() {
+        return None;
+    }
+
+    let parts: Vec<&str> = needle.split('\n').collect();
+    let last_row = storage.line_count().saturating_sub(1);
+    let from_row = from.row.min(last_row);
+    let from_byte = storage.char_to_byte(from_row, from.col);
+    let found = (from_row..=last_row)
+        .find_map(|row| {
+            let min_byte = if row == from_row { from_byte } else { 0 };
+            find_in_row(storage, &parts, row, min_byte)
+        })
+        .or_else(|| (0..=from_row).find_map(|row| find_in_row(storage, &parts, row, 0)))?;
+    Some(found)
+}
+
+// Start of the first occurrence of the needle split into `parts` at '\n'
+// that begins on line `row` at or after byte `min_byte`.
+fn find_in_row<S: TextStorage + ?Sized>(
+    storage: &S,
+    parts: &[&str],
+    row: usize,
+    min_byte: usize,
+) -> Option<Cursor> {
+    let line = storage.line(row)?;
+    let byte = match parts {
+        [] => return None,
+        [part] => line.get(min_byte..)?.find(part)? + min_byte,
+        [first, middle @ .., last] => {
+            // Only the end of this line can start a match that spans lines.
+            let start = line.len().checked_sub(first.len())?;
+            let fits = start >= min_byte
+                && line.ends_with(first)
+                && middle
+                    .iter()
+                    .enumerate()
+                    .all(|(offset, part)| storage.line(row + 1 + offset) == Some(*part))
+                && storage
+                    .line(row + parts.len() - 1)
+                    .is_some_and(|line| line.starts_with(last));
+            if !fits {
+                return None;
+            }
+            start
+        }
+    };
+    Some(Cursor::new(row, storage.byte_to_char(row, byte)))
+}
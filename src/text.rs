@@ -1,3 +1,9 @@
+use std::borrow::Cow;
+
+pub(crate) fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
 pub(crate) fn char_to_byte_index(text: &str, char_index: usize) -> usize {
     if char_index == 0 {
         return 0;
@@ -12,16 +18,1211 @@ pub(crate) fn char_to_byte_index(text: &str, char_index: usize) -> usize {
     text.len()
 }
 
-pub(crate) fn slice_line(text: &str, start_col: usize, width: usize) -> String {
+// Invalid UTF-8 bytes read in lossless mode are stored as escape units: one
+// char each, taken from the last 128 code points of plane 16 (private use),
+// so byte `b` becomes U+10FF00 + b.
+const ESCAPE_BASE: u32 = 0x10_ff00;
+
+// `byte` is 0x80 or above; lower bytes are always valid UTF-8.
+pub(crate) fn escape_byte(byte: u8) -> char {
+    char::from_u32(ESCAPE_BASE + u32::from(byte)).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+pub(crate) fn escaped_byte(ch: char) -> Option<u8> {
+    let byte = u32::from(ch).checked_sub(ESCAPE_BASE)?;
+    (0x80..=0xff).contains(&byte).then_some(byte as u8)
+}
+
+// Screen cells a char takes up, like `wcwidth`: 0 for C0/C1 controls
+// (tabs go through `cell_width`), combining marks, ZWJ, format chars, and
+// Hangul medial/final jamo; 2 for East Asian Wide and
+// Fullwidth chars and emoji; 4 for escape units, which render as `\xNN`.
+// Clusters are as wide as the sum of their chars.
+pub(crate) fn char_width(ch: char) -> usize {
+    let cp = u32::from(ch);
+    if cp < 0x300 {
+        return usize::from(!ch.is_control() && cp != 0xad);
+    }
+    if escaped_byte(ch).is_some() {
+        return 4;
+    }
+    if in_ranges(cp, ZERO_WIDTH)
+        || (in_ranges(cp, GRAPHEME_EXTEND) && !(0x1f3fb..=0x1f3ff).contains(&cp))
+        || in_ranges(cp, CONTROL)
+    {
+        return 0;
+    }
+    if in_ranges(cp, WIDE) { 2 } else { 1 }
+}
+
+// Cells `ch` takes when it starts at display column `col`: a tab reaches
+// the next multiple of `tab_width` (at least 1), other chars use
+// `char_width`.
+pub(crate) fn cell_width(ch: char, col: usize, tab_width: usize) -> usize {
+    if ch == '\t' {
+        let tab_width = tab_width.max(1);
+        tab_width - col % tab_width
+    } else {
+        char_width(ch)
+    }
+}
+
+// Display column reached after chars `start_col..col` of `text` when char
+// `start_col` is drawn at display column `origin`.
+pub(crate) fn display_col_from(
+    text: &str,
+    start_col: usize,
+    col: usize,
+    origin: usize,
+    tab_width: usize,
+) -> usize {
+    text.chars()
+        .take(col)
+        .skip(start_col)
+        .fold(origin, |display, ch| {
+            display + cell_width(ch, display, tab_width)
+        })
+}
+
+// Display column reached after drawing all of `text` from display column
+// `origin`.
+pub(crate) fn display_end(text: &str, origin: usize, tab_width: usize) -> usize {
+    text.chars().fold(origin, |display, ch| {
+        display + cell_width(ch, display, tab_width)
+    })
+}
+
+// Char range `slice_line` draws for the same display columns.
+pub(crate) fn visible_char_range(
+    text: &str,
+    start_col: usize,
+    width: usize,
+    tab_width: usize,
+) -> (usize, usize) {
+    let end_col = start_col + width;
+    let mut start = None;
+    let mut end = 0;
+    let mut col = 0;
+    for (idx, ch) in text.chars().enumerate() {
+        let ch_width = cell_width(ch, col, tab_width);
+        if ch_width == 0 && col > end_col || ch_width > 0 && col >= end_col {
+            break;
+        }
+        let visible = if ch_width == 0 {
+            col > start_col || start_col == 0
+        } else {
+            col + ch_width > start_col
+        };
+        if visible && start.is_none() {
+            start = Some(idx);
+        }
+        end = idx + 1;
+        col += ch_width;
+    }
+    (start.unwrap_or(end), end)
+}
+
+// Splits `text` into screen rows of at most `width` display columns and
+// returns each row's char range. Rows after the first start at display
+// column `indent` (ignored unless it leaves room for a char). With
+// `at_words`, a row that would split a word ends after its last whitespace
+// instead, if it has one. Every row holds at least one char, and zero-width
+// chars stay on the row of the char before them.
+pub(crate) fn wrap_line(
+    text: &str,
+    width: usize,
+    tab_width: usize,
+    at_words: bool,
+    indent: usize,
+) -> Vec<(usize, usize)> {
+    let chars: Vec<char> = text.chars().collect();
     if width == 0 {
-        return String::new();
+        return vec![(0, chars.len())];
+    }
+
+    let indent = wrap_indent(indent, width);
+    let mut rows = Vec::new();
+    let mut start = 0;
+    let mut col = 0;
+    let mut last_space = None;
+    let mut idx = 0;
+    while idx < chars.len() {
+        let ch = chars[idx];
+        let ch_width = cell_width(ch, col, tab_width);
+        if col + ch_width > width && idx > start {
+            let end = match last_space {
+                Some(space) if at_words && !ch.is_whitespace() => space,
+                _ => idx,
+            };
+            rows.push((start, end));
+            start = end;
+            idx = end;
+            col = indent;
+            last_space = None;
+            continue;
+        }
+
+        col += ch_width;
+        idx += 1;
+        if ch.is_whitespace() {
+            last_space = Some(idx);
+        }
+    }
+    rows.push((start, chars.len()));
+    rows
+}
+
+// The indent `wrap_line` actually uses for continuation rows.
+pub(crate) fn wrap_indent(indent: usize, width: usize) -> usize {
+    if indent < width { indent } else { 0 }
+}
+
+// The part of `text` that falls in display columns
+// `start_col..start_col + width`. Tabs are expanded to spaces up to the next
+// tab stop and escape units to `\xNN`; a wide char cut by either edge is
+// replaced by spaces for its visible cells, control chars are dropped so
+// they cannot drive the terminal, and other zero-width chars stay with the
+// char before them.
+pub(crate) fn slice_line(text: &str, start_col: usize, width: usize, tab_width: usize) -> String {
+    let end_col = start_col + width;
+    let mut sliced = String::new();
+    let mut col = 0;
+    for ch in text.chars() {
+        let ch_width = cell_width(ch, col, tab_width);
+        if ch_width == 0 {
+            if !ch.is_control() && (col > start_col || start_col == 0) && col <= end_col {
+                sliced.push(ch);
+            }
+            continue;
+        }
+        if col >= end_col {
+            break;
+        }
+
+        let ch_end = col + ch_width;
+        if ch_end > start_col {
+            let from = start_col.saturating_sub(col);
+            let to = (end_col - col).min(ch_width);
+            match escaped_byte(ch) {
+                Some(byte) => sliced.push_str(&format!("\\x{byte:02X}")[from..to]),
+                None if ch == '\t' || from > 0 || to < ch_width => {
+                    sliced.extend(std::iter::repeat_n(' ', to - from));
+                }
+                None => sliced.push(ch),
+            }
+        }
+        col = ch_end;
+    }
+    sliced
+}
+
+// Converts "\r\n" and lone '\r' line breaks to '\n'.
+pub(crate) fn normalize_line_endings(text: &str) -> Cow<'_, str> {
+    if !text.contains('\r') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut normalized = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\r' {
+            chars.next_if_eq(&'\n');
+            normalized.push('\n');
+        } else {
+            normalized.push(ch);
+        }
+    }
+    Cow::Owned(normalized)
+}
+
+// Chars between char→byte checkpoints in a non-ASCII line.
+const CHECKPOINT_STRIDE: usize = 128;
+
+// Cached facts about one line. ASCII lines map chars to bytes directly; other
+// lines keep the byte index of every `CHECKPOINT_STRIDE`th char so lookups
+// only walk from the nearest checkpoint.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct LineInfo {
+    chars: usize,
+    ascii: bool,
+    // Leading chars that are printable ASCII, so each takes exactly one
+    // display column.
+    plain_prefix: usize,
+    // `checkpoints[k]` is the byte index of char `(k + 1) * CHECKPOINT_STRIDE`.
+    checkpoints: Vec<usize>,
+}
+
+impl LineInfo {
+    pub(crate) fn new(line: &str) -> Self {
+        let mut info = Self {
+            chars: 0,
+            ascii: true,
+            plain_prefix: 0,
+            checkpoints: Vec::new(),
+        };
+        info.refresh(line, 0);
+        info
+    }
+
+    pub(crate) fn chars(&self) -> usize {
+        self.chars
+    }
+
+    pub(crate) fn plain_prefix(&self) -> usize {
+        self.plain_prefix
+    }
+
+    // Brings the cache up to date after an edit that left the first
+    // `from_col` chars of the line untouched. Only the tail is rescanned.
+    pub(crate) fn refresh(&mut self, line: &str, from_col: usize) {
+        let (start_col, start_byte) = if self.ascii {
+            let col = from_col.min(self.chars).min(line.len());
+            (col, col)
+        } else {
+            let keep = (from_col / CHECKPOINT_STRIDE).min(self.checkpoints.len());
+            self.checkpoints.truncate(keep);
+            match keep {
+                0 => (0, 0),
+                _ => (keep * CHECKPOINT_STRIDE, self.checkpoints[keep - 1]),
+            }
+        };
+
+        // Any other char in the untouched prefix still ends it.
+        let keep_plain = self.plain_prefix < from_col && self.plain_prefix < self.chars;
+        if self.ascii && line[start_byte..].is_ascii() {
+            // Still ASCII: a byte scan is enough, with no checkpoints.
+            if !keep_plain {
+                self.plain_prefix = line.as_bytes()[start_byte..]
+                    .iter()
+                    .position(|byte| !(b' '..=b'~').contains(byte))
+                    .map_or(line.len(), |offset| start_col + offset);
+            }
+            self.chars = line.len();
+            self.checkpoints.clear();
+            return;
+        }
+
+        let mut first_special = None;
+        let mut tail_checkpoints = Vec::new();
+        let mut col = start_col;
+        for (offset, ch) in line[start_byte..].char_indices() {
+            if col > start_col && col % CHECKPOINT_STRIDE == 0 {
+                tail_checkpoints.push(start_byte + offset);
+            }
+            if first_special.is_none() && !(' '..='~').contains(&ch) {
+                first_special = Some(col);
+            }
+            col += 1;
+        }
+
+        if !keep_plain {
+            self.plain_prefix = first_special.unwrap_or(col);
+        }
+        let was_ascii = self.ascii;
+        self.chars = col;
+        self.ascii = col == line.len();
+        if self.ascii {
+            self.checkpoints.clear();
+            return;
+        }
+        if was_ascii {
+            // The untouched prefix was ASCII, so its checkpoints are implied.
+            self.checkpoints = (1..=start_col / CHECKPOINT_STRIDE)
+                .map(|step| step * CHECKPOINT_STRIDE)
+                .collect();
+        }
+        self.checkpoints.extend(tail_checkpoints);
     }
 
-    let start = char_to_byte_index(text, start_col);
-    let end = char_to_byte_index(text, start_col + width);
-    if start >= end || start >= text.len() {
-        return String::new();
+    // Byte index of char `col` in `line`, clamped to the line's length.
+    pub(crate) fn char_to_byte(&self, line: &str, col: usize) -> usize {
+        if col >= self.chars {
+            return line.len();
+        }
+        if self.ascii {
+            return col;
+        }
+
+        let step = (col / CHECKPOINT_STRIDE).min(self.checkpoints.len());
+        let (start_col, start_byte) = match step {
+            0 => (0, 0),
+            _ => (step * CHECKPOINT_STRIDE, self.checkpoints[step - 1]),
+        };
+        line[start_byte..]
+            .char_indices()
+            .nth(col - start_col)
+            .map_or(line.len(), |(offset, _)| start_byte + offset)
     }
 
-    text[start..end].to_string()
+    // Char column of byte index `byte` (a char boundary) in `line`.
+    pub(crate) fn byte_to_char(&self, line: &str, byte: usize) -> usize {
+        let byte = byte.min(line.len());
+        if self.ascii {
+            return byte;
+        }
+
+        let step = self
+            .checkpoints
+            .partition_point(|&checkpoint| checkpoint <= byte);
+        let (start_col, start_byte) = match step {
+            0 => (0, 0),
+            _ => (step * CHECKPOINT_STRIDE, self.checkpoints[step - 1]),
+        };
+        start_col + line[start_byte..byte].chars().count()
+    }
 }
+
+// Grapheme_Cluster_Break values from UAX #29 (Unicode 15). The Indic conjunct
+// rule (GB9c) is not applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GraphemeBreak {
+    Other,
+    Control,
+    Extend,
+    Zwj,
+    RegionalIndicator,
+    Prepend,
+    SpacingMark,
+    L,
+    V,
+    T,
+    Lv,
+    Lvt,
+}
+
+fn grapheme_break(ch: char) -> GraphemeBreak {
+    let cp = u32::from(ch);
+    match cp {
+        0x00..=0x1f | 0x7f..=0x9f => GraphemeBreak::Control,
+        0x20..=0x7e => GraphemeBreak::Other,
+        0x200d => GraphemeBreak::Zwj,
+        0x1f1e6..=0x1f1ff => GraphemeBreak::RegionalIndicator,
+        0x1100..=0x115f | 0xa960..=0xa97c => GraphemeBreak::L,
+        0x1160..=0x11a7 | 0xd7b0..=0xd7c6 => GraphemeBreak::V,
+        0x11a8..=0x11ff | 0xd7cb..=0xd7fb => GraphemeBreak::T,
+        0xac00..=0xd7a3 if (cp - 0xac00) % 28 == 0 => GraphemeBreak::Lv,
+        0xac00..=0xd7a3 => GraphemeBreak::Lvt,
+        // Escape units always stand alone.
+        _ if escaped_byte(ch).is_some() => GraphemeBreak::Control,
+        _ if in_ranges(cp, GRAPHEME_EXTEND) => GraphemeBreak::Extend,
+        _ if in_ranges(cp, SPACING_MARK) => GraphemeBreak::SpacingMark,
+        _ if in_ranges(cp, PREPEND) => GraphemeBreak::Prepend,
+        _ if in_ranges(cp, CONTROL) => GraphemeBreak::Control,
+        _ => GraphemeBreak::Other,
+    }
+}
+
+fn in_ranges(cp: u32, ranges: &[(u32, u32)]) -> bool {
+    ranges
+        .binary_search_by(|&(start, end)| {
+            if end < cp {
+                std::cmp::Ordering::Less
+            } else if start > cp {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+fn is_extended_pictographic(ch: char) -> bool {
+    in_ranges(u32::from(ch), EXTENDED_PICTOGRAPHIC)
+}
+
+// Feeds chars left to right and reports where clusters start.
+#[derive(Default)]
+struct Segmenter {
+    prev: Option<GraphemeBreak>,
+    // The chars since the last Extended_Pictographic are all Extend.
+    pictographic_run: bool,
+    // The previous char is a ZWJ that ends such a run.
+    zwj_after_pictographic: bool,
+    // Regional indicators immediately before the next char.
+    regional_run: usize,
+}
+
+impl Segmenter {
+    // Whether a cluster boundary comes before `ch`.
+    fn push(&mut self, ch: char) -> bool {
+        use GraphemeBreak::*;
+
+        let current = grapheme_break(ch);
+        let pictographic = is_extended_pictographic(ch);
+        let boundary = match (self.prev, current) {
+            (None, _) => true,
+            (Some(Control), _) | (_, Control) => true,
+            (Some(L), L | V | Lv | Lvt) => false,
+            (Some(Lv | V), V | T) => false,
+            (Some(Lvt | T), T) => false,
+            (_, Extend | Zwj | SpacingMark) => false,
+            (Some(Prepend), _) => false,
+            (Some(Zwj), _) if pictographic && self.zwj_after_pictographic => false,
+            (Some(RegionalIndicator), RegionalIndicator) => self.regional_run.is_multiple_of(2),
+            _ => true,
+        };
+
+        self.zwj_after_pictographic = current == Zwj && self.pictographic_run;
+        self.pictographic_run = pictographic || (current == Extend && self.pictographic_run);
+        self.regional_run = if current == RegionalIndicator {
+            self.regional_run + 1
+        } else {
+            0
+        };
+        self.prev = Some(current);
+        boundary
+    }
+}
+
+// A byte index at or before `byte` that is certainly a cluster boundary: the
+// start of the text, or an ASCII char not preceded by a Prepend char.
+fn cluster_scan_start(text: &str, byte: usize) -> usize {
+    let mut start = byte;
+    while start > 0 {
+        let ascii = text.as_bytes()[start].is_ascii();
+        let prev = text[..start].chars().next_back();
+        if ascii && prev.is_none_or(|prev| grapheme_break(prev) != GraphemeBreak::Prepend) {
+            break;
+        }
+        start -= prev.map_or(1, char::len_utf8);
+    }
+    start
+}
+
+// Whether clusters around `byte` (a char in `text`) can be found from `text`
+// alone when it is a slice of a longer line: scanning back from `byte`
+// reaches a certain boundary after the slice's start.
+pub(crate) fn has_cluster_start_before(text: &str, byte: usize) -> bool {
+    byte < text.len() && cluster_scan_start(text, byte) > 0
+}
+
+// Byte index of the first grapheme cluster boundary after `byte`.
+pub(crate) fn next_grapheme_boundary(text: &str, byte: usize) -> usize {
+    if byte >= text.len() {
+        return text.len();
+    }
+
+    let start = cluster_scan_start(text, byte);
+    let mut segmenter = Segmenter::default();
+    for (offset, ch) in text[start..].char_indices() {
+        let idx = start + offset;
+        if segmenter.push(ch) && idx > byte {
+            return idx;
+        }
+    }
+    text.len()
+}
+
+// Byte index of the last grapheme cluster boundary before `byte`.
+pub(crate) fn prev_grapheme_boundary(text: &str, byte: usize) -> usize {
+    let byte = byte.min(text.len());
+    let Some(prev) = text[..byte].chars().next_back() else {
+        return 0;
+    };
+
+    let start = cluster_scan_start(text, byte - prev.len_utf8());
+    let mut segmenter = Segmenter::default();
+    let mut boundary = start;
+    for (offset, ch) in text[start..byte].char_indices() {
+        if segmenter.push(ch) {
+            boundary = start + offset;
+        }
+    }
+    boundary
+}
+
+const GRAPHEME_EXTEND: &[(u32, u32)] = &[
+    (0x0300, 0x036f),
+    (0x0483, 0x0489),
+    (0x0591, 0x05bd),
+    (0x05bf, 0x05bf),
+    (0x05c1, 0x05c2),
+    (0x05c4, 0x05c5),
+    (0x05c7, 0x05c7),
+    (0x0610, 0x061a),
+    (0x064b, 0x065f),
+    (0x0670, 0x0670),
+    (0x06d6, 0x06dc),
+    (0x06df, 0x06e4),
+    (0x06e7, 0x06e8),
+    (0x06ea, 0x06ed),
+    (0x0711, 0x0711),
+    (0x0730, 0x074a),
+    (0x07a6, 0x07b0),
+    (0x07eb, 0x07f3),
+    (0x07fd, 0x07fd),
+    (0x0816, 0x0819),
+    (0x081b, 0x0823),
+    (0x0825, 0x0827),
+    (0x0829, 0x082d),
+    (0x0859, 0x085b),
+    (0x0898, 0x089f),
+    (0x08ca, 0x08e1),
+    (0x08e3, 0x0902),
+    (0x093a, 0x093a),
+    (0x093c, 0x093c),
+    (0x0941, 0x0948),
+    (0x094d, 0x094d),
+    (0x0951, 0x0957),
+    (0x0962, 0x0963),
+    (0x0981, 0x0981),
+    (0x09bc, 0x09bc),
+    (0x09be, 0x09be),
+    (0x09c1, 0x09c4),
+    (0x09cd, 0x09cd),
+    (0x09d7, 0x09d7),
+    (0x09e2, 0x09e3),
+    (0x09fe, 0x09fe),
+    (0x0a01, 0x0a02),
+    (0x0a3c, 0x0a3c),
+    (0x0a41, 0x0a42),
+    (0x0a47, 0x0a48),
+    (0x0a4b, 0x0a4d),
+    (0x0a51, 0x0a51),
+    (0x0a70, 0x0a71),
+    (0x0a75, 0x0a75),
+    (0x0a81, 0x0a82),
+    (0x0abc, 0x0abc),
+    (0x0ac1, 0x0ac5),
+    (0x0ac7, 0x0ac8),
+    (0x0acd, 0x0acd),
+    (0x0ae2, 0x0ae3),
+    (0x0afa, 0x0aff),
+    (0x0b01, 0x0b01),
+    (0x0b3c, 0x0b3c),
+    (0x0b3e, 0x0b3f),
+    (0x0b41, 0x0b44),
+    (0x0b4d, 0x0b4d),
+    (0x0b55, 0x0b57),
+    (0x0b62, 0x0b63),
+    (0x0b82, 0x0b82),
+    (0x0bbe, 0x0bbe),
+    (0x0bc0, 0x0bc0),
+    (0x0bcd, 0x0bcd),
+    (0x0bd7, 0x0bd7),
+    (0x0c00, 0x0c00),
+    (0x0c04, 0x0c04),
+    (0x0c3c, 0x0c3c),
+    (0x0c3e, 0x0c40),
+    (0x0c46, 0x0c48),
+    (0x0c4a, 0x0c4d),
+    (0x0c55, 0x0c56),
+    (0x0c62, 0x0c63),
+    (0x0c81, 0x0c81),
+    (0x0cbc, 0x0cbc),
+    (0x0cbf, 0x0cbf),
+    (0x0cc2, 0x0cc2),
+    (0x0cc6, 0x0cc6),
+    (0x0ccc, 0x0ccd),
+    (0x0cd5, 0x0cd6),
+    (0x0ce2, 0x0ce3),
+    (0x0d00, 0x0d01),
+    (0x0d3b, 0x0d3c),
+    (0x0d3e, 0x0d3e),
+    (0x0d41, 0x0d44),
+    (0x0d4d, 0x0d4d),
+    (0x0d57, 0x0d57),
+    (0x0d62, 0x0d63),
+    (0x0d81, 0x0d81),
+    (0x0dca, 0x0dca),
+    (0x0dcf, 0x0dcf),
+    (0x0dd2, 0x0dd4),
+    (0x0dd6, 0x0dd6),
+    (0x0ddf, 0x0ddf),
+    (0x0e31, 0x0e31),
+    (0x0e34, 0x0e3a),
+    (0x0e47, 0x0e4e),
+    (0x0eb1, 0x0eb1),
+    (0x0eb4, 0x0ebc),
+    (0x0ec8, 0x0ece),
+    (0x0f18, 0x0f19),
+    (0x0f35, 0x0f35),
+    (0x0f37, 0x0f37),
+    (0x0f39, 0x0f39),
+    (0x0f71, 0x0f7e),
+    (0x0f80, 0x0f84),
+    (0x0f86, 0x0f87),
+    (0x0f8d, 0x0f97),
+    (0x0f99, 0x0fbc),
+    (0x0fc6, 0x0fc6),
+    (0x102d, 0x1030),
+    (0x1032, 0x1037),
+    (0x1039, 0x103a),
+    (0x103d, 0x103e),
+    (0x1058, 0x1059),
+    (0x105e, 0x1060),
+    (0x1071, 0x1074),
+    (0x1082, 0x1082),
+    (0x1085, 0x1086),
+    (0x108d, 0x108d),
+    (0x109d, 0x109d),
+    (0x135d, 0x135f),
+    (0x1712, 0x1714),
+    (0x1732, 0x1733),
+    (0x1752, 0x1753),
+    (0x1772, 0x1773),
+    (0x17b4, 0x17b5),
+    (0x17b7, 0x17bd),
+    (0x17c6, 0x17c6),
+    (0x17c9, 0x17d3),
+    (0x17dd, 0x17dd),
+    (0x180b, 0x180d),
+    (0x180f, 0x180f),
+    (0x1885, 0x1886),
+    (0x18a9, 0x18a9),
+    (0x1920, 0x1922),
+    (0x1927, 0x1928),
+    (0x1932, 0x1932),
+    (0x1939, 0x193b),
+    (0x1a17, 0x1a18),
+    (0x1a1b, 0x1a1b),
+    (0x1a56, 0x1a56),
+    (0x1a58, 0x1a5e),
+    (0x1a60, 0x1a60),
+    (0x1a62, 0x1a62),
+    (0x1a65, 0x1a6c),
+    (0x1a73, 0x1a7c),
+    (0x1a7f, 0x1a7f),
+    (0x1ab0, 0x1ace),
+    (0x1b00, 0x1b03),
+    (0x1b34, 0x1b3a),
+    (0x1b3c, 0x1b3c),
+    (0x1b42, 0x1b42),
+    (0x1b6b, 0x1b73),
+    (0x1b80, 0x1b81),
+    (0x1ba2, 0x1ba5),
+    (0x1ba8, 0x1ba9),
+    (0x1bab, 0x1bad),
+    (0x1be6, 0x1be6),
+    (0x1be8, 0x1be9),
+    (0x1bed, 0x1bed),
+    (0x1bef, 0x1bf1),
+    (0x1c2c, 0x1c33),
+    (0x1c36, 0x1c37),
+    (0x1cd0, 0x1cd2),
+    (0x1cd4, 0x1ce0),
+    (0x1ce2, 0x1ce8),
+    (0x1ced, 0x1ced),
+    (0x1cf4, 0x1cf4),
+    (0x1cf8, 0x1cf9),
+    (0x1dc0, 0x1dff),
+    (0x200c, 0x200c),
+    (0x20d0, 0x20f0),
+    (0x2cef, 0x2cf1),
+    (0x2d7f, 0x2d7f),
+    (0x2de0, 0x2dff),
+    (0x302a, 0x302f),
+    (0x3099, 0x309a),
+    (0xa66f, 0xa672),
+    (0xa674, 0xa67d),
+    (0xa69e, 0xa69f),
+    (0xa6f0, 0xa6f1),
+    (0xa802, 0xa802),
+    (0xa806, 0xa806),
+    (0xa80b, 0xa80b),
+    (0xa825, 0xa826),
+    (0xa82c, 0xa82c),
+    (0xa8c4, 0xa8c5),
+    (0xa8e0, 0xa8f1),
+    (0xa8ff, 0xa8ff),
+    (0xa926, 0xa92d),
+    (0xa947, 0xa951),
+    (0xa980, 0xa982),
+    (0xa9b3, 0xa9b3),
+    (0xa9b6, 0xa9b9),
+    (0xa9bc, 0xa9bd),
+    (0xa9e5, 0xa9e5),
+    (0xaa29, 0xaa2e),
+    (0xaa31, 0xaa32),
+    (0xaa35, 0xaa36),
+    (0xaa43, 0xaa43),
+    (0xaa4c, 0xaa4c),
+    (0xaa7c, 0xaa7c),
+    (0xaab0, 0xaab0),
+    (0xaab2, 0xaab4),
+    (0xaab7, 0xaab8),
+    (0xaabe, 0xaabf),
+    (0xaac1, 0xaac1),
+    (0xaaec, 0xaaed),
+    (0xaaf6, 0xaaf6),
+    (0xabe5, 0xabe5),
+    (0xabe8, 0xabe8),
+    (0xabed, 0xabed),
+    (0xfb1e, 0xfb1e),
+    (0xfe00, 0xfe0f),
+    (0xfe20, 0xfe2f),
+    (0xff9e, 0xff9f),
+    (0x101fd, 0x101fd),
+    (0x102e0, 0x102e0),
+    (0x10376, 0x1037a),
+    (0x10a01, 0x10a03),
+    (0x10a05, 0x10a06),
+    (0x10a0c, 0x10a0f),
+    (0x10a38, 0x10a3a),
+    (0x10a3f, 0x10a3f),
+    (0x10ae5, 0x10ae6),
+    (0x10d24, 0x10d27),
+    (0x10eab, 0x10eac),
+    (0x10f46, 0x10f50),
+    (0x11001, 0x11001),
+    (0x11038, 0x11046),
+    (0x11070, 0x11070),
+    (0x11073, 0x11074),
+    (0x1107f, 0x11081),
+    (0x110b3, 0x110b6),
+    (0x110b9, 0x110ba),
+    (0x110c2, 0x110c2),
+    (0x11100, 0x11102),
+    (0x11127, 0x1112b),
+    (0x1112d, 0x11134),
+    (0x11173, 0x11173),
+    (0x11180, 0x11181),
+    (0x111b6, 0x111be),
+    (0x111c9, 0x111cc),
+    (0x111cf, 0x111cf),
+    (0x1122f, 0x11231),
+    (0x11234, 0x11234),
+    (0x11236, 0x11237),
+    (0x1123e, 0x1123e),
+    (0x112df, 0x112df),
+    (0x112e3, 0x112ea),
+    (0x11300, 0x11301),
+    (0x1133b, 0x1133c),
+    (0x1133e, 0x1133e),
+    (0x11340, 0x11340),
+    (0x11357, 0x11357),
+    (0x11366, 0x1136c),
+    (0x11370, 0x11374),
+    (0x11438, 0x1143f),
+    (0x11442, 0x11444),
+    (0x11446, 0x11446),
+    (0x1145e, 0x1145e),
+    (0x114b0, 0x114b0),
+    (0x114b3, 0x114b8),
+    (0x114ba, 0x114ba),
+    (0x114bd, 0x114bd),
+    (0x114bf, 0x114c0),
+    (0x114c2, 0x114c3),
+    (0x115af, 0x115af),
+    (0x115b2, 0x115b5),
+    (0x115bc, 0x115bd),
+    (0x115bf, 0x115c0),
+    (0x115dc, 0x115dd),
+    (0x11633, 0x1163a),
+    (0x1163d, 0x1163d),
+    (0x1163f, 0x11640),
+    (0x116ab, 0x116ab),
+    (0x116ad, 0x116ad),
+    (0x116b0, 0x116b5),
+    (0x116b7, 0x116b7),
+    (0x1171d, 0x1171f),
+    (0x11722, 0x11725),
+    (0x11727, 0x1172b),
+    (0x1182f, 0x11837),
+    (0x11839, 0x1183a),
+    (0x16af0, 0x16af4),
+    (0x16b30, 0x16b36),
+    (0x16f4f, 0x16f4f),
+    (0x16f8f, 0x16f92),
+    (0x16fe4, 0x16fe4),
+    (0x1bc9d, 0x1bc9e),
+    (0x1cf00, 0x1cf2d),
+    (0x1cf30, 0x1cf46),
+    (0x1d165, 0x1d165),
+    (0x1d167, 0x1d169),
+    (0x1d16e, 0x1d172),
+    (0x1d17b, 0x1d182),
+    (0x1d185, 0x1d18b),
+    (0x1d1aa, 0x1d1ad),
+    (0x1d242, 0x1d244),
+    (0x1da00, 0x1da36),
+    (0x1da3b, 0x1da6c),
+    (0x1da75, 0x1da75),
+    (0x1da84, 0x1da84),
+    (0x1da9b, 0x1da9f),
+    (0x1daa1, 0x1daaf),
+    (0x1e000, 0x1e006),
+    (0x1e008, 0x1e018),
+    (0x1e01b, 0x1e021),
+    (0x1e023, 0x1e024),
+    (0x1e026, 0x1e02a),
+    (0x1e08f, 0x1e08f),
+    (0x1e130, 0x1e136),
+    (0x1e2ae, 0x1e2ae),
+    (0x1e2ec, 0x1e2ef),
+    (0x1e4ec, 0x1e4ef),
+    (0x1e8d0, 0x1e8d6),
+    (0x1e944, 0x1e94a),
+    (0x1f3fb, 0x1f3ff),
+    (0xe0020, 0xe007f),
+    (0xe0100, 0xe01ef),
+];
+
+const SPACING_MARK: &[(u32, u32)] = &[
+    (0x0903, 0x0903),
+    (0x093b, 0x093b),
+    (0x093e, 0x0940),
+    (0x0949, 0x094c),
+    (0x094e, 0x094f),
+    (0x0982, 0x0983),
+    (0x09bf, 0x09c0),
+    (0x09c7, 0x09c8),
+    (0x09cb, 0x09cc),
+    (0x0a03, 0x0a03),
+    (0x0a3e, 0x0a40),
+    (0x0a83, 0x0a83),
+    (0x0abe, 0x0ac0),
+    (0x0ac9, 0x0ac9),
+    (0x0acb, 0x0acc),
+    (0x0b02, 0x0b03),
+    (0x0b40, 0x0b40),
+    (0x0b47, 0x0b48),
+    (0x0b4b, 0x0b4c),
+    (0x0bbf, 0x0bbf),
+    (0x0bc1, 0x0bc2),
+    (0x0bc6, 0x0bc8),
+    (0x0bca, 0x0bcc),
+    (0x0c01, 0x0c03),
+    (0x0c41, 0x0c44),
+    (0x0c82, 0x0c83),
+    (0x0cbe, 0x0cbe),
+    (0x0cc0, 0x0cc1),
+    (0x0cc3, 0x0cc4),
+    (0x0cc7, 0x0cc8),
+    (0x0cca, 0x0ccb),
+    (0x0cf3, 0x0cf3),
+    (0x0d02, 0x0d03),
+    (0x0d3f, 0x0d40),
+    (0x0d46, 0x0d48),
+    (0x0d4a, 0x0d4c),
+    (0x0d82, 0x0d83),
+    (0x0dd0, 0x0dd1),
+    (0x0dd8, 0x0dde),
+    (0x0df2, 0x0df3),
+    (0x0e33, 0x0e33),
+    (0x0eb3, 0x0eb3),
+    (0x0f3e, 0x0f3f),
+    (0x0f7f, 0x0f7f),
+    (0x1031, 0x1031),
+    (0x103b, 0x103c),
+    (0x1056, 0x1057),
+    (0x1084, 0x1084),
+    (0x1715, 0x1715),
+    (0x1734, 0x1734),
+    (0x17b6, 0x17b6),
+    (0x17be, 0x17c5),
+    (0x17c7, 0x17c8),
+    (0x1923, 0x1926),
+    (0x1929, 0x192b),
+    (0x1930, 0x1931),
+    (0x1933, 0x1938),
+    (0x1a19, 0x1a1a),
+    (0x1a55, 0x1a55),
+    (0x1a57, 0x1a57),
+    (0x1a6d, 0x1a72),
+    (0x1b04, 0x1b04),
+    (0x1b3b, 0x1b3b),
+    (0x1b3d, 0x1b41),
+    (0x1b43, 0x1b44),
+    (0x1b82, 0x1b82),
+    (0x1ba1, 0x1ba1),
+    (0x1ba6, 0x1ba7),
+    (0x1baa, 0x1baa),
+    (0x1be7, 0x1be7),
+    (0x1bea, 0x1bec),
+    (0x1bee, 0x1bee),
+    (0x1bf2, 0x1bf3),
+    (0x1c24, 0x1c2b),
+    (0x1c34, 0x1c35),
+    (0x1ce1, 0x1ce1),
+    (0x1cf7, 0x1cf7),
+    (0xa823, 0xa824),
+    (0xa827, 0xa827),
+    (0xa880, 0xa881),
+    (0xa8b4, 0xa8c3),
+    (0xa952, 0xa953),
+    (0xa983, 0xa983),
+    (0xa9b4, 0xa9b5),
+    (0xa9ba, 0xa9bb),
+    (0xa9be, 0xa9c0),
+    (0xaa2f, 0xaa30),
+    (0xaa33, 0xaa34),
+    (0xaa4d, 0xaa4d),
+    (0xaaeb, 0xaaeb),
+    (0xaaee, 0xaaef),
+    (0xaaf5, 0xaaf5),
+    (0xabe3, 0xabe4),
+    (0xabe6, 0xabe7),
+    (0xabe9, 0xabea),
+    (0xabec, 0xabec),
+    (0x11000, 0x11000),
+    (0x11002, 0x11002),
+    (0x11082, 0x11082),
+    (0x110b0, 0x110b2),
+    (0x110b7, 0x110b8),
+    (0x1112c, 0x1112c),
+    (0x11145, 0x11146),
+    (0x11182, 0x11182),
+    (0x111b3, 0x111b5),
+    (0x111bf, 0x111c0),
+    (0x111ce, 0x111ce),
+    (0x1122c, 0x1122e),
+    (0x11232, 0x11233),
+    (0x11235, 0x11235),
+    (0x112e0, 0x112e2),
+    (0x11302, 0x11303),
+    (0x1133f, 0x1133f),
+    (0x11341, 0x11344),
+    (0x11347, 0x11348),
+    (0x1134b, 0x1134d),
+    (0x11362, 0x11363),
+    (0x11435, 0x11437),
+    (0x11440, 0x11441),
+    (0x11445, 0x11445),
+    (0x114b1, 0x114b2),
+    (0x114b9, 0x114b9),
+    (0x114bb, 0x114bc),
+    (0x114be, 0x114be),
+    (0x114c1, 0x114c1),
+    (0x115b0, 0x115b1),
+    (0x115b8, 0x115bb),
+    (0x115be, 0x115be),
+    (0x11630, 0x11632),
+    (0x1163b, 0x1163c),
+    (0x1163e, 0x1163e),
+    (0x116ac, 0x116ac),
+    (0x116ae, 0x116af),
+    (0x116b6, 0x116b6),
+    (0x11726, 0x11726),
+    (0x1182c, 0x1182e),
+    (0x11838, 0x11838),
+    (0x16f51, 0x16f87),
+    (0x16ff0, 0x16ff1),
+    (0x1d166, 0x1d166),
+    (0x1d16d, 0x1d16d),
+];
+
+const PREPEND: &[(u32, u32)] = &[
+    (0x0600, 0x0605),
+    (0x06dd, 0x06dd),
+    (0x070f, 0x070f),
+    (0x0890, 0x0891),
+    (0x08e2, 0x08e2),
+    (0x0d4e, 0x0d4e),
+    (0x110bd, 0x110bd),
+    (0x110cd, 0x110cd),
+    (0x111c2, 0x111c3),
+    (0x1193f, 0x1193f),
+    (0x11941, 0x11941),
+    (0x11a3a, 0x11a3a),
+    (0x11a84, 0x11a89),
+    (0x11d46, 0x11d46),
+    (0x11f02, 0x11f02),
+];
+
+// Control chars beyond C0/C1: line/paragraph separators and format chars
+// that are not ZWJ, ZWNJ, or Prepend.
+const CONTROL: &[(u32, u32)] = &[
+    (0x00ad, 0x00ad),
+    (0x061c, 0x061c),
+    (0x180e, 0x180e),
+    (0x200b, 0x200b),
+    (0x200e, 0x200f),
+    (0x2028, 0x202e),
+    (0x2060, 0x206f),
+    (0xfeff, 0xfeff),
+    (0xfff0, 0xfffb),
+    (0x13430, 0x1343f),
+    (0x1bca0, 0x1bca3),
+    (0x1d173, 0x1d17a),
+    (0xe0000, 0xe001f),
+    (0xe0080, 0xe00ff),
+    (0xe01f0, 0xe0fff),
+];
+
+const EXTENDED_PICTOGRAPHIC: &[(u32, u32)] = &[
+    (0x00a9, 0x00a9),
+    (0x00ae, 0x00ae),
+    (0x203c, 0x203c),
+    (0x2049, 0x2049),
+    (0x2122, 0x2122),
+    (0x2139, 0x2139),
+    (0x2194, 0x2199),
+    (0x21a9, 0x21aa),
+    (0x231a, 0x231b),
+    (0x2328, 0x2328),
+    (0x2388, 0x2388),
+    (0x23cf, 0x23cf),
+    (0x23e9, 0x23f3),
+    (0x23f8, 0x23fa),
+    (0x24c2, 0x24c2),
+    (0x25aa, 0x25ab),
+    (0x25b6, 0x25b6),
+    (0x25c0, 0x25c0),
+    (0x25fb, 0x25fe),
+    (0x2600, 0x2605),
+    (0x2607, 0x2612),
+    (0x2614, 0x2685),
+    (0x2690, 0x2705),
+    (0x2708, 0x2712),
+    (0x2714, 0x2714),
+    (0x2716, 0x2716),
+    (0x271d, 0x271d),
+    (0x2721, 0x2721),
+    (0x2728, 0x2728),
+    (0x2733, 0x2734),
+    (0x2744, 0x2744),
+    (0x2747, 0x2747),
+    (0x274c, 0x274c),
+    (0x274e, 0x274e),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2763, 0x2767),
+    (0x2795, 0x2797),
+    (0x27a1, 0x27a1),
+    (0x27b0, 0x27b0),
+    (0x27bf, 0x27bf),
+    (0x2934, 0x2935),
+    (0x2b05, 0x2b07),
+    (0x2b1b, 0x2b1c),
+    (0x2b50, 0x2b50),
+    (0x2b55, 0x2b55),
+    (0x3030, 0x3030),
+    (0x303d, 0x303d),
+    (0x3297, 0x3297),
+    (0x3299, 0x3299),
+    (0x1f000, 0x1f0ff),
+    (0x1f10d, 0x1f10f),
+    (0x1f12f, 0x1f12f),
+    (0x1f16c, 0x1f171),
+    (0x1f17e, 0x1f17f),
+    (0x1f18e, 0x1f18e),
+    (0x1f191, 0x1f19a),
+    (0x1f1ad, 0x1f1e5),
+    (0x1f201, 0x1f20f),
+    (0x1f21a, 0x1f21a),
+    (0x1f22f, 0x1f22f),
+    (0x1f232, 0x1f23a),
+    (0x1f23c, 0x1f23f),
+    (0x1f249, 0x1f3fa),
+    (0x1f400, 0x1f53d),
+    (0x1f546, 0x1f64f),
+    (0x1f680, 0x1f6ff),
+    (0x1f774, 0x1f77f),
+    (0x1f7d5, 0x1f7ff),
+    (0x1f80c, 0x1f80f),
+    (0x1f848, 0x1f84f),
+    (0x1f85a, 0x1f85f),
+    (0x1f888, 0x1f88f),
+    (0x1f8ae, 0x1f8ff),
+    (0x1f90c, 0x1f93a),
+    (0x1f93c, 0x1f945),
+    (0x1f947, 0x1faff),
+    (0x1fc00, 0x1fffd),
+];
+
+// Zero-width chars not covered by GRAPHEME_EXTEND or CONTROL.
+const ZERO_WIDTH: &[(u32, u32)] = &[(0x1160, 0x11ff), (0x200d, 0x200d), (0xd7b0, 0xd7ff)];
+
+// East Asian Wide (W) and Fullwidth (F) ranges, including emoji presented
+// as wide by default.
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115f),
+    (0x231a, 0x231b),
+    (0x2329, 0x232a),
+    (0x23e9, 0x23ec),
+    (0x23f0, 0x23f0),
+    (0x23f3, 0x23f3),
+    (0x25fd, 0x25fe),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267f, 0x267f),
+    (0x2693, 0x2693),
+    (0x26a1, 0x26a1),
+    (0x26aa, 0x26ab),
+    (0x26bd, 0x26be),
+    (0x26c4, 0x26c5),
+    (0x26ce, 0x26ce),
+    (0x26d4, 0x26d4),
+    (0x26ea, 0x26ea),
+    (0x26f2, 0x26f3),
+    (0x26f5, 0x26f5),
+    (0x26fa, 0x26fa),
+    (0x26fd, 0x26fd),
+    (0x2705, 0x2705),
+    (0x270a, 0x270b),
+    (0x2728, 0x2728),
+    (0x274c, 0x274c),
+    (0x274e, 0x274e),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27b0, 0x27b0),
+    (0x27bf, 0x27bf),
+    (0x2b1b, 0x2b1c),
+    (0x2b50, 0x2b50),
+    (0x2b55, 0x2b55),
+    (0x2e80, 0x303e),
+    (0x3041, 0x33ff),
+    (0x3400, 0x4dbf),
+    (0x4e00, 0x9fff),
+    (0xa000, 0xa4cf),
+    (0xa960, 0xa97f),
+    (0xac00, 0xd7a3),
+    (0xf900, 0xfaff),
+    (0xfe10, 0xfe19),
+    (0xfe30, 0xfe6f),
+    (0xff00, 0xff60),
+    (0xffe0, 0xffe6),
+    (0x16fe0, 0x16fe4),
+    (0x17000, 0x18aff),
+    (0x1b000, 0x1b2ff),
+    (0x1f004, 0x1f004),
+    (0x1f0cf, 0x1f0cf),
+    (0x1f18e, 0x1f18e),
+    (0x1f191, 0x1f19a),
+    (0x1f200, 0x1f202),
+    (0x1f210, 0x1f23b),
+    (0x1f240, 0x1f248),
+    (0x1f250, 0x1f251),
+    (0x1f260, 0x1f265),
+    (0x1f300, 0x1f320),
+    (0x1f32d, 0x1f335),
+    (0x1f337, 0x1f37c),
+    (0x1f37e, 0x1f393),
+    (0x1f3a0, 0x1f3ca),
+    (0x1f3cf, 0x1f3d3),
+    (0x1f3e0, 0x1f3f0),
+    (0x1f3f4, 0x1f3f4),
+    (0x1f3f8, 0x1f43e),
+    (0x1f440, 0x1f440),
+    (0x1f442, 0x1f4fc),
+    (0x1f4ff, 0x1f53d),
+    (0x1f54b, 0x1f54e),
+    (0x1f550, 0x1f567),
+    (0x1f57a, 0x1f57a),
+    (0x1f595, 0x1f596),
+    (0x1f5a4, 0x1f5a4),
+    (0x1f5fb, 0x1f64f),
+    (0x1f680, 0x1f6c5),
+    (0x1f6cc, 0x1f6cc),
+    (0x1f6d0, 0x1f6d2),
+    (0x1f6d5, 0x1f6d7),
+    (0x1f6dc, 0x1f6df),
+    (0x1f6eb, 0x1f6ec),
+    (0x1f6f4, 0x1f6fc),
+    (0x1f7e0, 0x1f7eb),
+    (0x1f7f0, 0x1f7f0),
+    (0x1f90c, 0x1f93a),
+    (0x1f93c, 0x1f945),
+    (0x1f947, 0x1f9ff),
+    (0x1fa70, 0x1fa7c),
+    (0x1fa80, 0x1fa88),
+    (0x1fa90, 0x1fabd),
+    (0x1fabf, 0x1fac5),
+    (0x1face, 0x1fadb),
+    (0x1fae0, 0x1fae8),
+    (0x1faf0, 0x1faf8),
+    (0x20000, 0x2fffd),
+    (0x30000, 0x3fffd),
+];
@@ -1,4 +1,4 @@
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Cursor {
     pub row: usize,
     pub col: usize,
@@ -8,12 +8,113 @@ impl Cursor {
     pub fn new(row: usize, col: usize) -> Self {
         Self { row, col }
     }
+
+    // Where this position ends up after text spanning `start..end` is
+    // inserted. Positions at the insertion point move with the text.
+    pub(crate) fn shifted_by_insert(self, start: Cursor, end: Cursor) -> Cursor {
+        if self < start {
+            self
+        } else if self.row == start.row {
+            Cursor::new(end.row, end.col + (self.col - start.col))
+        } else {
+            Cursor::new(self.row + (end.row - start.row), self.col)
+        }
+    }
+
+    // Where this position ends up after the text `start..end` is removed.
+    pub(crate) fn shifted_by_remove(self, start: Cursor, end: Cursor) -> Cursor {
+        if self <= start {
+            self
+        } else if self <= end {
+            start
+        } else if self.row == end.row {
+            Cursor::new(start.row, start.col + (self.col - end.col))
+        } else {
+            Cursor::new(self.row - (end.row - start.row), self.col)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: Cursor,
+    pub head: Cursor,
+}
+
+impl Selection {
+    pub fn new(anchor: Cursor, head: Cursor) -> Self {
+        Self { anchor, head }
+    }
+
+    pub fn caret(cursor: Cursor) -> Self {
+        Self {
+            anchor: cursor,
+            head: cursor,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    pub fn start(&self) -> Cursor {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> Cursor {
+        self.anchor.max(self.head)
+    }
+
+    pub(crate) fn map(self, f: impl Fn(Cursor) -> Cursor) -> Selection {
+        Selection::new(f(self.anchor), f(self.head))
+    }
+}
+
+// A rectangle in (row, char column) space. Columns may lie past the end of
+// short lines; the block covers columns `left_col()..right_col()` of every row
+// from `top_row()` to `bottom_row()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockSelection {
+    pub anchor: Cursor,
+    pub head: Cursor,
+}
+
+impl BlockSelection {
+    pub fn new(anchor: Cursor, head: Cursor) -> Self {
+        Self { anchor, head }
+    }
+
+    pub fn top_row(&self) -> usize {
+        self.anchor.row.min(self.head.row)
+    }
+
+    pub fn bottom_row(&self) -> usize {
+        self.anchor.row.max(self.head.row)
+    }
+
+    pub fn left_col(&self) -> usize {
+        self.anchor.col.min(self.head.col)
+    }
+
+    pub fn right_col(&self) -> usize {
+        self.anchor.col.max(self.head.col)
+    }
+
+    pub(crate) fn with_col(self, col: usize) -> BlockSelection {
+        BlockSelection::new(
+            Cursor::new(self.anchor.row, col),
+            Cursor::new(self.head.row, col),
+        )
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Viewport {
     pub row_offset: usize,
     pub col_offset: usize,
+    // With soft wrap, the number of visual rows of line `row_offset` scrolled
+    // off the top.
+    pub wrap_offset: usize,
     pub height: usize,
     pub width: usize,
 }
@@ -23,12 +124,40 @@ impl Viewport {
         Self {
             row_offset: 0,
             col_offset: 0,
+            wrap_offset: 0,
             height,
             width,
         }
     }
 }
 
+// One screen row of `visible_lines`: chars `start_col..end_col` of buffer
+// line `row`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VisualRow {
+    pub row: usize,
+    pub start_col: usize,
+    pub end_col: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Action {
     MoveLeft,
@@ -37,8 +166,61 @@ pub enum Action {
     MoveWordRight,
     MoveUp,
     MoveDown,
+    MoveVisualUp,
+    MoveVisualDown,
+    MoveVisualLineStart,
+    MoveVisualLineEnd,
+    MoveLineStart,
+    MoveLineEnd,
+    MoveFirstNonWhitespace,
+    MoveBufferStart,
+    MoveBufferEnd,
+    GotoLine(usize),
+    GotoPosition(Cursor),
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    SelectLeft,
+    SelectRight,
+    SelectWordLeft,
+    SelectWordRight,
+    SelectUp,
+    SelectDown,
+    SelectVisualUp,
+    SelectVisualDown,
+    SelectVisualLineStart,
+    SelectVisualLineEnd,
+    SelectLineStart,
+    SelectLineEnd,
+    SelectFirstNonWhitespace,
+    SelectBufferStart,
+    SelectBufferEnd,
+    SelectAll,
+    AddCursorAbove,
+    AddCursorBelow,
+    AddNextOccurrence,
+    RemoveSecondaryCursors,
+    BlockSelectLeft,
+    BlockSelectRight,
+    BlockSelectUp,
+    BlockSelectDown,
+    SelectRegister(char),
+    Copy,
+    Cut,
+    Paste,
+    PasteBefore,
+    KillWordForward,
+    KillWordBackward,
+    KillToLineEnd,
+    Yank,
+    YankPop,
     Insert(char),
     DeleteBackward,
     DeleteForward,
     Newline,
+    Undo,
+    Redo,
+    Earlier,
+    Later,
 }
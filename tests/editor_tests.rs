@@ -1,4 +1,8 @@
-use edit_core::{Action, Buffer, Cursor, Editor, Viewport};
+use edit_core::{
+    Action, BlockSelection, Buffer, Clipboard, Cursor, Editor, Encoding, EncodingError,
+    HistoryError, LineEnding, RegisterContent, RegisterKind, Selection, TextStorage, Viewport,
+    VisualRow,
+};
 
 #[test]
 fn buffer_from_text_roundtrip() {
@@ -6,8 +10,9 @@ fn buffer_from_text_roundtrip() {
     let buffer = Buffer::from_text("hello\nworld\n");
 
     // Assert
-    assert_eq!(buffer.line_count(), 3);
-    assert_eq!(buffer.line(2), Some(""));
+    assert_eq!(buffer.line_count(), 2);
+    assert_eq!(buffer.line(1), Some("world"));
+    assert!(buffer.has_final_newline());
     assert_eq!(buffer.as_text(), "hello\nworld\n");
 
     // Init
@@ -222,3 +227,2458 @@ fn move_word_crosses_lines_consistently() {
     editor.apply(Action::MoveWordRight);
     assert_eq!(editor.cursor(), Cursor::new(1, 7)); // after "bar"
 }
+
+#[test]
+fn undo_redo_restores_text_and_cursor() {
+    // Init
+    let buffer = Buffer::from_text("ab\ncd");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 10));
+
+    // Act
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::Newline);
+    editor.apply(Action::Insert('x'));
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a\nxb\ncd");
+    assert_eq!(editor.cursor(), Cursor::new(1, 1));
+
+    // Act
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a\nb\ncd");
+    assert_eq!(editor.cursor(), Cursor::new(1, 0));
+
+    // Act
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "ab\ncd");
+    assert_eq!(editor.cursor(), Cursor::new(0, 1));
+    assert!(!editor.can_undo());
+
+    // Act
+    editor.apply(Action::Redo);
+    editor.apply(Action::Redo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a\nxb\ncd");
+    assert_eq!(editor.cursor(), Cursor::new(1, 1));
+    assert!(!editor.can_redo());
+}
+
+#[test]
+fn undo_reverts_line_joins_and_new_edit_clears_redo() {
+    // Init
+    let buffer = Buffer::from_text("ab\ncd");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 10));
+
+    // Act
+    editor.apply(Action::MoveDown);
+    editor.apply(Action::DeleteBackward);
+    editor.apply(Action::DeleteForward);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "abd");
+
+    // Act
+    assert!(editor.undo());
+    assert!(editor.undo());
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "ab\ncd");
+    assert_eq!(editor.cursor(), Cursor::new(1, 0));
+
+    // Act
+    editor.apply(Action::Insert('z'));
+
+    // Assert
+    assert!(!editor.can_redo());
+    assert!(!editor.redo());
+    assert_eq!(editor.buffer().as_text(), "ab\nzcd");
+}
+
+#[test]
+fn undo_coalesces_runs_of_same_kind_edits() {
+    // Init
+    let buffer = Buffer::from_text("");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    for ch in "hello".chars() {
+        editor.apply(Action::Insert(ch));
+    }
+    editor.apply(Action::Newline);
+    for ch in "world".chars() {
+        editor.apply(Action::Insert(ch));
+    }
+    editor.apply(Action::DeleteBackward);
+    editor.apply(Action::DeleteBackward);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "hello\nwor");
+
+    // Act
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "hello\nworld");
+
+    // Act
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "hello\n");
+
+    // Act
+    editor.apply(Action::Undo);
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "");
+    assert!(!editor.can_undo());
+}
+
+#[test]
+fn undo_runs_break_on_cursor_movement() {
+    // Init
+    let buffer = Buffer::from_text("");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    editor.apply(Action::Insert('a'));
+    editor.apply(Action::Insert('b'));
+    editor.apply(Action::MoveLeft);
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::Insert('c'));
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "ab");
+    assert_eq!(editor.cursor(), Cursor::new(0, 2));
+}
+
+#[test]
+fn transaction_undoes_compound_edit_atomically() {
+    // Init
+    let buffer = Buffer::from_text("fn main() {");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+    for _ in 0..11 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Act
+    editor.begin_transaction();
+    editor.apply(Action::Newline);
+    for _ in 0..4 {
+        editor.apply(Action::Insert(' '));
+    }
+    assert!(editor.in_transaction());
+    editor.end_transaction();
+
+    // Assert
+    assert!(!editor.in_transaction());
+    assert_eq!(editor.buffer().as_text(), "fn main() {\n    ");
+
+    // Act
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "fn main() {");
+    assert_eq!(editor.cursor(), Cursor::new(0, 11));
+
+    // Act
+    editor.apply(Action::Redo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "fn main() {\n    ");
+    assert_eq!(editor.cursor(), Cursor::new(1, 4));
+}
+
+#[test]
+fn undo_tree_keeps_branches_after_undo_then_edit() {
+    // Init
+    let buffer = Buffer::from_text("");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    editor.apply(Action::Insert('a'));
+    editor.apply(Action::Undo);
+    editor.apply(Action::Insert('b'));
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "b");
+    assert_eq!(editor.current_state(), 2);
+    assert_eq!(editor.state_count(), 3);
+
+    // Act
+    editor.apply(Action::Earlier);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a");
+    assert_eq!(editor.current_state(), 1);
+
+    // Act
+    editor.apply(Action::Earlier);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "");
+    assert_eq!(editor.redo_branch_count(), 2);
+
+    // Act
+    assert!(editor.select_redo_branch(1));
+    editor.apply(Action::Redo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "b");
+
+    // Act
+    editor.apply(Action::Later);
+
+    // Assert
+    assert!(!editor.later(1));
+    assert_eq!(editor.buffer().as_text(), "b");
+}
+
+#[test]
+fn goto_state_and_time_travel_across_branches() {
+    // Init
+    let buffer = Buffer::from_text("x");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    editor.apply(Action::Insert('1'));
+    editor.apply(Action::Newline);
+    editor.apply(Action::Undo);
+    editor.apply(Action::Undo);
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::Insert('2'));
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "x2");
+
+    // Act
+    assert!(editor.goto_state(2));
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "1\nx");
+    assert_eq!(editor.cursor(), Cursor::new(1, 0));
+
+    // Act
+    assert!(editor.goto_time(std::time::UNIX_EPOCH));
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "x");
+    assert_eq!(editor.current_state(), 0);
+
+    // Act
+    let latest = editor.state_time(3).unwrap();
+    assert!(editor.goto_time(latest));
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "x2");
+    assert_eq!(editor.cursor(), Cursor::new(0, 2));
+}
+
+#[test]
+fn goto_time_orders_states_by_recording_not_by_clock() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text(""), Viewport::new(5, 20));
+    editor.apply(Action::Insert('a'));
+    editor.apply(Action::Newline);
+    editor.apply(Action::Insert('b'));
+    let saved = String::from_utf8(editor.save_history()).unwrap();
+    // The clock was set back before the last edit.
+    let mut secs = [10, 100, 200, 50].into_iter();
+    let saved: String = saved
+        .lines()
+        .map(|line| {
+            let mut fields: Vec<String> = line.split(' ').map(str::to_string).collect();
+            if fields[0] == "node" {
+                fields[3] = secs.next().unwrap().to_string();
+            }
+            fields.join(" ") + "\n"
+        })
+        .collect();
+    let mut reopened = Editor::new(Buffer::from_text("a\nb"), Viewport::new(5, 20));
+    reopened.load_history(saved.as_bytes()).unwrap();
+    reopened.goto_state(0);
+
+    // Act
+    let moved = reopened.goto_time(std::time::UNIX_EPOCH + std::time::Duration::from_secs(60));
+
+    // Assert
+    assert!(moved);
+    assert_eq!(reopened.current_state(), 3);
+    assert_eq!(reopened.buffer().as_text(), "a\nb");
+}
+
+#[test]
+fn saved_history_reattaches_to_matching_buffer() {
+    // Init
+    let buffer = Buffer::from_text("one");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+    for _ in 0..3 {
+        editor.apply(Action::MoveRight);
+    }
+    editor.apply(Action::Newline);
+    for ch in "two\nthree".chars() {
+        editor.apply(Action::Insert(ch));
+    }
+    editor.apply(Action::Undo);
+    editor.apply(Action::Insert('!'));
+
+    // Act
+    let saved = editor.save_history();
+    let text = editor.buffer().as_text();
+    let mut reopened = Editor::new(Buffer::from_text(&text), Viewport::new(5, 20));
+    let loaded = reopened.load_history(&saved);
+
+    // Assert
+    assert_eq!(loaded, Ok(()));
+    assert_eq!(reopened.current_state(), editor.current_state());
+    assert_eq!(reopened.state_count(), editor.state_count());
+
+    // Act
+    reopened.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(reopened.buffer().as_text(), "one\ntwo\n");
+    assert_eq!(reopened.cursor(), Cursor::new(2, 0));
+
+    // Act
+    reopened.apply(Action::Later);
+
+    // Assert
+    assert_eq!(reopened.buffer().as_text(), "one\ntwo\nthree");
+}
+
+#[test]
+fn save_history_keeps_an_open_transaction_open() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("abc"), Viewport::new(5, 20));
+    editor.begin_transaction();
+    editor.apply(Action::Insert('x'));
+    editor.apply(Action::Insert('y'));
+
+    // Act
+    let saved = editor.save_history();
+    let mut reopened = Editor::new(Buffer::from_text("xyabc"), Viewport::new(5, 20));
+    let loaded = reopened.load_history(&saved);
+
+    // Assert
+    assert!(editor.in_transaction());
+    assert_eq!(loaded, Ok(()));
+    assert!(reopened.undo());
+    assert_eq!(reopened.buffer().as_text(), "abc");
+    assert!(!reopened.can_undo());
+
+    // Act
+    editor.apply(Action::Insert('z'));
+    editor.end_transaction();
+    editor.undo();
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "abc");
+    assert!(!editor.can_undo());
+}
+
+#[test]
+fn load_history_reads_version_1_data() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("abc"), Viewport::new(5, 20));
+    editor.apply(Action::Insert('x'));
+    let saved = String::from_utf8(editor.save_history()).unwrap();
+    let hash = saved.lines().nth(1).unwrap();
+    let version_1 = format!(
+        "edit_core-history 1\n{hash}\ncurrent 1\nnodes 2\n\
+         node 0 1 0 0 0 0 0 0 0\nnode 0 - 0 0 0 0 0 1 1\ni 0 0 1\nx\n"
+    );
+    let mut reopened = Editor::new(Buffer::from_text("xabc"), Viewport::new(5, 20));
+
+    // Act
+    let loaded = reopened.load_history(version_1.as_bytes());
+
+    // Assert
+    assert_eq!(loaded, Ok(()));
+    assert!(reopened.undo());
+    assert_eq!(reopened.buffer().as_text(), "abc");
+    assert_eq!(reopened.cursor(), Cursor::new(0, 0));
+}
+
+#[test]
+fn load_history_refuses_mismatched_or_corrupt_data() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("abc"), Viewport::new(5, 20));
+    editor.apply(Action::Insert('x'));
+    let saved = editor.save_history();
+
+    // Act
+    let mut other = Editor::new(Buffer::from_text("abc"), Viewport::new(5, 20));
+    let mismatch = other.load_history(&saved);
+
+    // Assert
+    assert_eq!(mismatch, Err(HistoryError::TextMismatch));
+    assert!(!other.can_undo());
+
+    // Act
+    let mut reopened = Editor::new(Buffer::from_text("xabc"), Viewport::new(5, 20));
+    let future = String::from_utf8(saved.clone())
+        .unwrap()
+        .replacen("history 2", "history 99", 1);
+    let unsupported = reopened.load_history(future.as_bytes());
+    let truncated = reopened.load_history(&saved[..saved.len() - 3]);
+    let huge_count = String::from_utf8(saved.clone()).unwrap().replacen(
+        "\nnodes 2\n",
+        "\nnodes 18446744073709551615\n",
+        1,
+    );
+    let oversized = reopened.load_history(huge_count.as_bytes());
+    let misplaced =
+        String::from_utf8(saved.clone())
+            .unwrap()
+            .replacen("\ni 0 0 1\n", "\ni 0 9 1\n", 1);
+    let out_of_range = reopened.load_history(misplaced.as_bytes());
+    let altered = String::from_utf8(saved.clone())
+        .unwrap()
+        .replacen("\nx\n", "\ny\n", 1);
+    let wrong_text = reopened.load_history(altered.as_bytes());
+
+    // Assert
+    assert_ne!(huge_count.as_bytes(), &saved[..]);
+    assert_eq!(unsupported, Err(HistoryError::UnsupportedVersion(99)));
+    assert!(matches!(truncated, Err(HistoryError::Malformed(_))));
+    assert!(matches!(oversized, Err(HistoryError::Malformed(_))));
+    assert_ne!(misplaced.as_bytes(), &saved[..]);
+    assert_ne!(altered.as_bytes(), &saved[..]);
+    assert!(matches!(out_of_range, Err(HistoryError::Malformed(_))));
+    assert!(matches!(wrong_text, Err(HistoryError::Malformed(_))));
+    assert!(!reopened.can_undo());
+}
+
+#[test]
+fn shift_motions_extend_selection_from_anchor() {
+    // Init
+    let buffer = Buffer::from_text("foo bar\nbaz");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    editor.apply(Action::SelectWordRight);
+    editor.apply(Action::SelectRight);
+
+    // Assert
+    assert_eq!(
+        editor.selection(),
+        Selection::new(Cursor::new(0, 0), Cursor::new(0, 4))
+    );
+    assert_eq!(editor.selected_text(), "foo ");
+
+    // Act
+    editor.apply(Action::SelectDown);
+
+    // Assert
+    assert_eq!(editor.selected_text(), "foo bar\nbaz");
+
+    // Act
+    editor.apply(Action::MoveLeft);
+
+    // Assert
+    assert!(editor.selection().is_empty());
+    assert_eq!(editor.cursor(), Cursor::new(0, 0));
+}
+
+#[test]
+fn edits_replace_or_remove_the_selection() {
+    // Init
+    let buffer = Buffer::from_text("hello\nworld");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    editor.set_selection(Selection::new(Cursor::new(1, 2), Cursor::new(0, 3)));
+    editor.apply(Action::Insert('P'));
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "helPrld");
+    assert_eq!(editor.cursor(), Cursor::new(0, 4));
+    assert!(editor.selection().is_empty());
+
+    // Act
+    editor.apply(Action::SelectWordLeft);
+    editor.apply(Action::DeleteForward);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "rld");
+
+    // Act
+    editor.apply(Action::SelectAll);
+    editor.apply(Action::DeleteBackward);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "");
+
+    // Act
+    editor.apply(Action::Undo);
+    editor.apply(Action::Undo);
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "hello\nworld");
+}
+
+#[test]
+fn multiple_cursors_edit_simultaneously() {
+    // Init
+    let buffer = Buffer::from_text("ab\ncd\nef");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::AddCursorBelow);
+    editor.apply(Action::AddCursorBelow);
+    editor.apply(Action::Insert('-'));
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a-b\nc-d\ne-f");
+    assert_eq!(editor.selections().len(), 3);
+    assert_eq!(editor.cursor(), Cursor::new(2, 2));
+
+    // Act
+    editor.apply(Action::Newline);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a-\nb\nc-\nd\ne-\nf");
+    let heads: Vec<Cursor> = editor.selections().iter().map(|s| s.head).collect();
+    assert_eq!(
+        heads,
+        vec![Cursor::new(1, 0), Cursor::new(3, 0), Cursor::new(5, 0)]
+    );
+
+    // Act
+    editor.apply(Action::DeleteBackward);
+    editor.apply(Action::DeleteBackward);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "ab\ncd\nef");
+
+    // Act
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a-b\nc-d\ne-f");
+    let heads: Vec<Cursor> = editor.selections().iter().map(|s| s.head).collect();
+    assert_eq!(
+        heads,
+        vec![Cursor::new(0, 2), Cursor::new(1, 2), Cursor::new(2, 2)]
+    );
+    assert_eq!(editor.cursor(), Cursor::new(2, 2));
+}
+
+#[test]
+fn undo_restores_every_cursor() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("ab\ncd"), Viewport::new(5, 20));
+    editor.add_cursor(Cursor::new(1, 0));
+    editor.apply(Action::Insert('-'));
+    let saved = editor.save_history();
+
+    // Act
+    editor.apply(Action::Undo);
+
+    // Assert
+    let heads: Vec<Cursor> = editor.selections().iter().map(|s| s.head).collect();
+    assert_eq!(heads, vec![Cursor::new(0, 0), Cursor::new(1, 0)]);
+    assert_eq!(editor.cursor(), Cursor::new(1, 0));
+
+    // Act
+    editor.apply(Action::Redo);
+
+    // Assert
+    let heads: Vec<Cursor> = editor.selections().iter().map(|s| s.head).collect();
+    assert_eq!(heads, vec![Cursor::new(0, 1), Cursor::new(1, 1)]);
+
+    // Act
+    let mut reopened = Editor::new(Buffer::from_text("-ab\n-cd"), Viewport::new(5, 20));
+    reopened.load_history(&saved).unwrap();
+    reopened.undo();
+
+    // Assert
+    assert_eq!(reopened.buffer().as_text(), "ab\ncd");
+    assert_eq!(reopened.selections().len(), 2);
+}
+
+#[test]
+fn colliding_cursors_merge() {
+    // Init
+    let buffer = Buffer::from_text("abc");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    editor.add_cursor(Cursor::new(0, 1));
+    editor.add_cursor(Cursor::new(0, 2));
+    editor.apply(Action::MoveLeft);
+
+    // Assert
+    assert_eq!(editor.selections().len(), 2);
+
+    // Act
+    editor.apply(Action::MoveLeft);
+
+    // Assert
+    assert_eq!(
+        editor.selections(),
+        vec![Selection::caret(Cursor::new(0, 0))]
+    );
+}
+
+#[test]
+fn add_next_occurrence_selects_matching_words() {
+    // Init
+    let buffer = Buffer::from_text("let foo = foo + 1;\nfoo();");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 40));
+    for _ in 0..5 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Act
+    editor.apply(Action::AddNextOccurrence);
+
+    // Assert
+    assert_eq!(editor.selected_text(), "foo");
+
+    // Act
+    editor.apply(Action::AddNextOccurrence);
+    editor.apply(Action::AddNextOccurrence);
+    editor.apply(Action::AddNextOccurrence);
+
+    // Assert
+    assert_eq!(editor.selections().len(), 3);
+
+    // Act
+    for ch in "bar".chars() {
+        editor.apply(Action::Insert(ch));
+    }
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "let bar = bar + 1;\nbar();");
+
+    // Act
+    editor.apply(Action::RemoveSecondaryCursors);
+
+    // Assert
+    assert_eq!(editor.selections().len(), 1);
+    assert_eq!(editor.cursor(), Cursor::new(1, 3));
+}
+
+#[test]
+fn add_next_occurrence_matches_across_lines_and_wraps() {
+    // Init
+    let buffer = Buffer::from_text("x ab\ncd ab\ncd ab\ncx");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 40));
+    editor.set_selection(Selection::new(Cursor::new(1, 3), Cursor::new(2, 2)));
+
+    // Act
+    editor.apply(Action::AddNextOccurrence);
+    editor.apply(Action::AddNextOccurrence);
+
+    // Assert
+    assert_eq!(
+        editor.selections(),
+        vec![
+            Selection::new(Cursor::new(0, 2), Cursor::new(1, 2)),
+            Selection::new(Cursor::new(1, 3), Cursor::new(2, 2)),
+        ]
+    );
+
+    // Act
+    editor.apply(Action::Insert('Z'));
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "x Z Z ab\ncx");
+}
+
+#[test]
+fn block_insert_pads_short_lines() {
+    // Init
+    let buffer = Buffer::from_text("alpha\nbe\ngamma");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+    for _ in 0..4 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Act
+    editor.apply(Action::BlockSelectDown);
+    editor.apply(Action::BlockSelectDown);
+    editor.apply(Action::Insert('|'));
+    editor.apply(Action::Insert('|'));
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "alph||a\nbe  ||\ngamm||a");
+    assert_eq!(
+        editor.block_selection(),
+        Some(BlockSelection::new(Cursor::new(0, 6), Cursor::new(2, 6)))
+    );
+
+    // Act
+    editor.apply(Action::DeleteBackward);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "alph|a\nbe  |\ngamm|a");
+
+    // Act
+    editor.apply(Action::Undo);
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "alpha\nbe\ngamma");
+    assert_eq!(editor.block_selection(), None);
+}
+
+#[test]
+fn block_selection_replaces_and_copies_rectangles() {
+    // Init
+    let buffer = Buffer::from_text("a1 b1\na2 b2\na3");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    editor.set_block_selection(BlockSelection::new(Cursor::new(0, 3), Cursor::new(2, 5)));
+
+    // Assert
+    assert_eq!(
+        editor.block_text(),
+        Some(vec!["b1".to_string(), "b2".to_string(), String::new()])
+    );
+
+    // Act
+    editor.apply(Action::DeleteForward);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a1 \na2 \na3");
+
+    // Act
+    editor.apply(Action::MoveDown);
+    editor.set_block_selection(BlockSelection::new(Cursor::new(0, 0), Cursor::new(1, 2)));
+    let copied = editor.block_text().unwrap();
+    editor.apply(Action::MoveUp);
+    editor.paste_block(&copied);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a1a1 \na2a2 \na3");
+
+    // Act
+    editor.set_block_selection(BlockSelection::new(Cursor::new(2, 0), Cursor::new(2, 2)));
+    let last = editor.block_text().unwrap();
+    editor.set_block_selection(BlockSelection::new(Cursor::new(2, 4), Cursor::new(2, 4)));
+    editor.apply(Action::MoveRight);
+    editor.paste_block(&[last[0].clone(), "zz".to_string()]);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a1a1 \na2a2 \na3a3\n  zz");
+}
+
+#[test]
+fn copy_and_paste_charwise_and_linewise() {
+    // Init
+    let buffer = Buffer::from_text("one two\nthree");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    editor.apply(Action::SelectWordRight);
+    editor.apply(Action::Copy);
+    editor.apply(Action::MoveDown);
+    editor.apply(Action::Paste);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "one two\nthroneee");
+    assert_eq!(editor.cursor(), Cursor::new(1, 6));
+    assert_eq!(editor.register('0'), Some(RegisterContent::charwise("one")));
+
+    // Act
+    editor.apply(Action::Cut);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "one two");
+    assert_eq!(
+        editor.register('"'),
+        Some(RegisterContent::linewise("throneee\n"))
+    );
+    assert_eq!(editor.register('-'), editor.register('"'));
+
+    // Act
+    editor.apply(Action::PasteBefore);
+    editor.apply(Action::Paste);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "throneee\nthroneee\none two");
+    assert_eq!(editor.cursor(), Cursor::new(1, 0));
+}
+
+#[test]
+fn named_registers_and_block_content() {
+    // Init
+    let buffer = Buffer::from_text("ab\ncd\nef");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    editor.apply(Action::SelectRegister('a'));
+    editor.apply(Action::Copy);
+    editor.apply(Action::MoveDown);
+    editor.apply(Action::SelectRegister('A'));
+    editor.apply(Action::Copy);
+
+    // Assert
+    assert_eq!(
+        editor.register('a'),
+        Some(RegisterContent::linewise("ab\ncd\n"))
+    );
+
+    // Act
+    editor.apply(Action::BlockSelectRight);
+    editor.apply(Action::BlockSelectDown);
+    editor.apply(Action::Cut);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "ab\nd\nf");
+    assert_eq!(
+        editor.register('"'),
+        Some(RegisterContent::new("c\ne", RegisterKind::Blockwise))
+    );
+
+    // Act
+    editor.apply(Action::MoveUp);
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::Paste);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "ab\ndc\nfe");
+
+    // Act
+    editor.apply(Action::SelectRegister('a'));
+    editor.apply(Action::PasteBefore);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "ab\nab\ncd\ndc\nfe");
+}
+
+#[derive(Default)]
+struct TestClipboard {
+    text: std::sync::Arc<std::sync::Mutex<String>>,
+}
+
+impl Clipboard for TestClipboard {
+    fn get(&mut self) -> Option<String> {
+        Some(self.text.lock().unwrap().clone())
+    }
+
+    fn set(&mut self, text: &str) {
+        *self.text.lock().unwrap() = text.to_string();
+    }
+}
+
+#[test]
+fn clipboard_register_uses_host_clipboard() {
+    // Init
+    let buffer = Buffer::from_text("hello");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+    let clipboard = TestClipboard::default();
+    let shared = clipboard.text.clone();
+    editor.set_clipboard(clipboard);
+
+    // Act
+    editor.apply(Action::SelectAll);
+    editor.apply(Action::SelectRegister('+'));
+    editor.apply(Action::Copy);
+
+    // Assert
+    assert_eq!(shared.lock().unwrap().as_str(), "hello");
+
+    // Act
+    *shared.lock().unwrap() = " world".to_string();
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::SelectRegister('+'));
+    editor.apply(Action::Paste);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "hello world");
+
+    // Act
+    *shared.lock().unwrap() = "a\r\nb\rc".to_string();
+    editor.apply(Action::SelectRegister('+'));
+    editor.apply(Action::Paste);
+
+    // Assert
+    assert_eq!(editor.buffer().line_count(), 3);
+    assert_eq!(editor.buffer().line(0), Some("hello worlda"));
+    assert_eq!(editor.buffer().as_text(), "hello worlda\nb\nc");
+}
+
+#[test]
+fn host_registers_and_block_rows_get_line_breaks_normalized() {
+    // Init
+    let buffer = Buffer::from_text("ab\ncd\nef");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+
+    // Act
+    editor.set_register('a', RegisterContent::charwise("1\r\n2\r3"));
+    editor.apply(Action::SelectRegister('a'));
+    editor.apply(Action::Paste);
+
+    // Assert
+    assert_eq!(
+        editor.register('a'),
+        Some(RegisterContent::charwise("1\n2\n3"))
+    );
+    assert_eq!(editor.buffer().as_text(), "1\n2\n3ab\ncd\nef");
+
+    // Act
+    editor.apply(Action::Undo);
+    editor.apply(Action::MoveDown);
+    editor.paste_block(&["x\r\ny", "z"]);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "ab\nxcd\nyef\nz");
+}
+
+#[test]
+fn consecutive_kills_append_to_one_entry() {
+    // Init
+    let buffer = Buffer::from_text("alpha beta gamma\ndelta");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 40));
+    for _ in 0..5 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Act
+    editor.apply(Action::KillWordForward);
+    editor.apply(Action::KillToLineEnd);
+    editor.apply(Action::KillToLineEnd);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "alphadelta");
+    assert_eq!(editor.kill_ring(), &[" beta gamma\n".to_string()]);
+
+    // Act
+    editor.apply(Action::KillWordBackward);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "delta");
+    assert_eq!(editor.kill_ring(), &["alpha beta gamma\n".to_string()]);
+
+    // Act
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::MoveLeft);
+    editor.apply(Action::KillToLineEnd);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "");
+    assert_eq!(editor.kill_ring().len(), 2);
+    assert_eq!(editor.kill_ring()[0], "delta");
+}
+
+#[test]
+fn yank_pop_cycles_older_kills_in_place() {
+    // Init
+    let buffer = Buffer::from_text("one two three");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 40));
+    for _ in 0..3 {
+        editor.apply(Action::KillWordForward);
+        editor.apply(Action::MoveRight);
+    }
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "  ");
+
+    // Act
+    editor.apply(Action::Yank);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "  three");
+
+    // Act
+    editor.apply(Action::YankPop);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "  two");
+
+    // Act
+    editor.apply(Action::YankPop);
+    editor.apply(Action::YankPop);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "  three");
+    assert_eq!(editor.cursor(), Cursor::new(0, 7));
+
+    // Act
+    editor.apply(Action::MoveLeft);
+    editor.apply(Action::YankPop);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "  three");
+}
+
+#[test]
+fn insert_str_splices_multiline_text_as_one_edit() {
+    // Init
+    let buffer = Buffer::from_text("start end");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+    for _ in 0..6 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Act
+    editor.insert_str("one\r\ntwo\rthree\n");
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "start one\ntwo\nthree\nend");
+    assert_eq!(editor.cursor(), Cursor::new(3, 0));
+    assert!(editor.is_dirty());
+
+    // Act
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "start end");
+    assert_eq!(editor.cursor(), Cursor::new(0, 6));
+}
+
+#[test]
+fn insert_str_handles_large_pastes_and_selections() {
+    // Init
+    let buffer = Buffer::from_text("a\nb");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+    let line = "x".repeat(999);
+    let pasted = vec![line.as_str(); 50].join("\n");
+
+    // Act
+    editor.apply(Action::SelectDown);
+    editor.insert_str(&pasted);
+
+    // Assert
+    assert_eq!(editor.buffer().line_count(), 50);
+    assert_eq!(editor.buffer().line(49).map(str::len), Some(1000));
+    assert_eq!(editor.cursor(), Cursor::new(49, 999));
+    assert_eq!(editor.viewport().row_offset, 45);
+
+    // Act
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a\nb");
+}
+
+#[test]
+fn insert_str_joins_an_open_transaction() {
+    // Init
+    let buffer = Buffer::from_text("a\nb");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 20));
+    editor.add_cursor(Cursor::new(1, 1));
+
+    // Act
+    editor.begin_transaction();
+    editor.insert_str("xy");
+    editor.apply(Action::Insert('z'));
+    editor.end_transaction();
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "xyza\nbxyz");
+
+    // Act
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a\nb");
+    assert!(!editor.can_undo());
+}
+
+#[test]
+fn buffer_char_offsets_round_trip() {
+    // Init
+    let buffer = Buffer::from_text("ab\n\ncdé\nf");
+
+    // Assert
+    assert_eq!(buffer.char_count(), 9);
+    assert_eq!(buffer.char_offset(Cursor::new(0, 2)), 2);
+    assert_eq!(buffer.char_offset(Cursor::new(1, 0)), 3);
+    assert_eq!(buffer.char_offset(Cursor::new(2, 3)), 7);
+    assert_eq!(buffer.char_offset(Cursor::new(3, 1)), 9);
+    for offset in 0..=buffer.char_count() {
+        let position = buffer.position_at(offset);
+        assert_eq!(buffer.char_offset(position), offset);
+    }
+    assert_eq!(buffer.position_at(100), Cursor::new(3, 1));
+}
+
+#[test]
+fn many_line_edits_match_plain_string_model() {
+    // Init
+    let initial = (0..200)
+        .map(|idx| format!("line {idx}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut model = initial.clone();
+    let mut editor = Editor::new(Buffer::from_text(&initial), Viewport::new(10, 40));
+    let mut seed: u64 = 42;
+    let mut next = |bound: usize| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed % bound as u64) as usize
+    };
+
+    for step in 0..400 {
+        // Act
+        let offset = next(model.chars().count() + 1);
+        let position = editor.buffer().position_at(offset);
+        editor.set_selection(Selection::caret(position));
+        let byte = model
+            .char_indices()
+            .nth(offset)
+            .map_or(model.len(), |(idx, _)| idx);
+        if step % 3 == 0 {
+            let len = (1 + next(30)).min(model[byte..].chars().count());
+            let end = editor.buffer().position_at(offset + len);
+            editor.set_selection(Selection::new(position, end));
+            editor.apply(Action::DeleteForward);
+            let end_byte = model[byte..]
+                .char_indices()
+                .nth(len)
+                .map_or(model.len(), |(idx, _)| byte + idx);
+            model.replace_range(byte..end_byte, "");
+        } else {
+            let text = match step % 4 {
+                0 => "x".to_string(),
+                1 => "\n".to_string(),
+                2 => format!("é{step}\nnew\n"),
+                _ => format!("{step}\n\n"),
+            };
+            editor.insert_str(&text);
+            model.insert_str(byte, &text);
+        }
+
+        // Assert
+        assert_eq!(editor.buffer().as_text(), model);
+        assert_eq!(editor.buffer().char_count(), model.chars().count());
+    }
+}
+
+// Minimal storage over plain lines; optionally read-only.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct VecStorage {
+    lines: Vec<String>,
+    read_only: bool,
+}
+
+impl VecStorage {
+    fn new(text: &str, read_only: bool) -> Self {
+        Self {
+            lines: text.split('\n').map(str::to_string).collect(),
+            read_only,
+        }
+    }
+}
+
+impl TextStorage for VecStorage {
+    fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn line(&self, row: usize) -> Option<&str> {
+        self.lines.get(row).map(String::as_str)
+    }
+
+    fn insert(&mut self, at: Cursor, text: &str) -> Cursor {
+        let byte = self.char_to_byte(at.row, at.col);
+        let tail = self.lines[at.row].split_off(byte);
+        let mut pieces = text.split('\n');
+        self.lines[at.row].push_str(pieces.next().unwrap_or_default());
+        let mut row = at.row;
+        for piece in pieces {
+            row += 1;
+            self.lines.insert(row, piece.to_string());
+        }
+        let col = self.line_len_chars(row);
+        self.lines[row].push_str(&tail);
+        Cursor::new(row, col)
+    }
+
+    fn remove(&mut self, start: Cursor, end: Cursor) -> String {
+        let removed = self.text_range(start, end);
+        let end_byte = self.char_to_byte(end.row, end.col);
+        let tail = self.lines[end.row][end_byte..].to_string();
+        let start_byte = self.char_to_byte(start.row, start.col);
+        self.lines.drain(start.row + 1..=end.row);
+        self.lines[start.row].truncate(start_byte);
+        self.lines[start.row].push_str(&tail);
+        removed
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+}
+
+#[test]
+fn editor_runs_on_custom_storage() {
+    // Init
+    let storage = VecStorage::new("héllo\nworld", false);
+    let mut editor = Editor::new(storage, Viewport::new(5, 40));
+
+    // Act
+    editor.apply(Action::MoveWordRight);
+    editor.apply(Action::Newline);
+    editor.insert_str("big\n");
+    editor.apply(Action::MoveDown);
+    editor.apply(Action::DeleteBackward);
+
+    // Assert
+    assert_eq!(editor.buffer().text(), "héllo\nbig\nworld");
+    assert_eq!(editor.cursor(), Cursor::new(2, 0));
+
+    // Act
+    editor.undo();
+    editor.undo();
+    editor.undo();
+
+    // Assert
+    assert_eq!(editor.buffer().text(), "héllo\nworld");
+    assert_eq!(editor.cursor(), Cursor::new(0, 5));
+}
+
+#[test]
+fn read_only_storage_ignores_edits() {
+    // Init
+    let storage = VecStorage::new("alpha beta\ngamma", true);
+    let mut editor = Editor::new(storage.clone(), Viewport::new(5, 40));
+
+    // Act
+    editor.apply(Action::SelectWordRight);
+    editor.apply(Action::Copy);
+    editor.apply(Action::Insert('x'));
+    editor.apply(Action::Cut);
+    editor.apply(Action::DeleteForward);
+    editor.apply(Action::Paste);
+    editor.paste_block(&["a", "b"]);
+    editor.apply(Action::MoveDown);
+
+    // Assert
+    assert_eq!(editor.buffer(), &storage);
+    assert!(!editor.is_dirty());
+    assert!(!editor.can_undo());
+    assert_eq!(
+        editor.register('"'),
+        Some(RegisterContent::charwise("alpha"))
+    );
+    assert_eq!(editor.cursor(), Cursor::new(1, 5));
+}
+
+#[test]
+fn read_only_storage_refuses_history() {
+    // Init
+    let mut writable = Editor::new(VecStorage::new("abc", false), Viewport::new(5, 40));
+    writable.apply(Action::Insert('x'));
+    let saved = writable.save_history();
+    let storage = VecStorage::new("xabc", true);
+    let mut editor = Editor::new(storage.clone(), Viewport::new(5, 40));
+
+    // Act
+    let loaded = editor.load_history(&saved);
+    let undone = editor.undo();
+    let redone = editor.redo();
+    let earlier = editor.earlier(1);
+    let went = editor.goto_state(0);
+    editor.apply(Action::Undo);
+
+    // Assert
+    assert_eq!(loaded, Err(HistoryError::ReadOnly));
+    assert!(!undone && !redone && !earlier && !went);
+    assert!(!editor.can_undo());
+    assert_eq!(editor.buffer(), &storage);
+}
+
+fn assert_line_lookups_match(buffer: &Buffer, row: usize) {
+    let line = buffer.line(row).unwrap();
+    assert_eq!(buffer.line_len_chars(row), line.chars().count());
+    for (col, (byte, _)) in line.char_indices().enumerate() {
+        assert_eq!(buffer.char_to_byte(row, col), byte);
+        assert_eq!(buffer.byte_to_char(row, byte), col);
+    }
+    assert_eq!(
+        buffer.char_to_byte(row, line.chars().count() + 5),
+        line.len()
+    );
+    assert_eq!(buffer.byte_to_char(row, line.len()), line.chars().count());
+    let plain = line
+        .chars()
+        .take_while(|ch| (' '..='~').contains(ch))
+        .count();
+    assert_eq!(buffer.plain_prefix_chars(row), plain);
+}
+
+#[test]
+fn long_line_metadata_stays_in_sync() {
+    // Init
+    let line = "abc".repeat(200);
+    let mut editor = Editor::new(Buffer::from_text(&line), Viewport::new(5, 40));
+
+    // Act
+    editor.set_selection(Selection::caret(Cursor::new(0, 600)));
+    for _ in 0..150 {
+        editor.apply(Action::Insert('é'));
+        editor.apply(Action::Insert('x'));
+    }
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 900));
+    assert_line_lookups_match(editor.buffer(), 0);
+
+    // Act
+    editor.set_selection(Selection::caret(Cursor::new(0, 5)));
+    editor.apply(Action::Insert('\t'));
+
+    // Assert
+    assert_eq!(editor.buffer().plain_prefix_chars(0), 5);
+    assert_line_lookups_match(editor.buffer(), 0);
+
+    // Act
+    editor.apply(Action::DeleteBackward);
+
+    // Assert
+    assert_eq!(editor.buffer().plain_prefix_chars(0), 600);
+    assert_line_lookups_match(editor.buffer(), 0);
+
+    // Act
+    editor.set_selection(Selection::caret(Cursor::new(0, 10)));
+    editor.insert_str("日本語\n");
+    editor.set_selection(Selection::new(Cursor::new(1, 300), Cursor::new(1, 500)));
+    editor.apply(Action::DeleteBackward);
+
+    // Assert
+    assert_line_lookups_match(editor.buffer(), 0);
+    assert_line_lookups_match(editor.buffer(), 1);
+
+    // Act
+    editor.set_selection(Selection::new(Cursor::new(1, 0), Cursor::new(1, 700)));
+    editor.apply(Action::DeleteBackward);
+    editor.set_selection(Selection::new(Cursor::new(0, 10), Cursor::new(1, 0)));
+    editor.apply(Action::DeleteBackward);
+
+    // Assert
+    assert_eq!(editor.buffer().line_count(), 1);
+    assert_line_lookups_match(editor.buffer(), 0);
+}
+
+#[test]
+fn crlf_buffer_strips_and_restores_line_endings() {
+    // Init
+    let buffer = Buffer::from_text("fn main() {\r\n    go();\r\n}");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 40));
+
+    // Assert
+    assert_eq!(editor.buffer().line_ending(), LineEnding::CrLf);
+    assert!(!editor.buffer().has_mixed_line_endings());
+    assert_eq!(editor.buffer().line(0), Some("fn main() {"));
+    assert_eq!(editor.visible_lines()[1], "    go();");
+
+    // Act
+    for _ in 0..5 {
+        editor.apply(Action::MoveWordRight);
+    }
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 11));
+
+    // Act
+    editor.apply(Action::Newline);
+
+    // Assert
+    assert_eq!(
+        editor.buffer().as_text(),
+        "fn main() {\r\n\r\n    go();\r\n}"
+    );
+}
+
+#[test]
+fn mixed_line_endings_are_reported_and_converted() {
+    // Init
+    let buffer = Buffer::from_text("a\r\nb\nc\r\nd\re");
+    let mut editor = Editor::new(buffer, Viewport::new(5, 40));
+
+    // Assert
+    assert_eq!(editor.buffer().line_count(), 5);
+    assert_eq!(editor.buffer().line_ending(), LineEnding::CrLf);
+    assert!(editor.buffer().has_mixed_line_endings());
+    assert_eq!(editor.buffer().as_text(), "a\r\nb\nc\r\nd\re");
+
+    // Act
+    editor.apply(Action::MoveDown);
+    editor.apply(Action::MoveLineEnd);
+    editor.apply(Action::Newline);
+    editor.apply(Action::Insert('x'));
+    editor.apply(Action::MoveDown);
+    editor.apply(Action::MoveDown);
+    editor.apply(Action::DeleteForward);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "a\r\nb\r\nx\nc\r\nde");
+    assert_eq!(
+        editor.buffer().to_bytes(Encoding::Utf8),
+        Ok(b"a\r\nb\r\nx\nc\r\nde".to_vec())
+    );
+
+    // Act
+    editor.set_line_ending(LineEnding::Lf);
+
+    // Assert
+    assert!(editor.is_dirty());
+    assert!(!editor.buffer().has_mixed_line_endings());
+    assert_eq!(editor.buffer().as_text(), "a\nb\nx\nc\nde");
+    assert_eq!(Buffer::from_text("a\nb\r").as_text(), "a\nb\r");
+    assert_eq!(Buffer::from_text("x\ry").line_ending(), LineEnding::Cr);
+    assert_eq!(Buffer::from_text("x").line_ending(), LineEnding::Lf);
+}
+
+#[test]
+fn bom_and_final_newline_round_trip() {
+    // Init
+    let text = "\u{feff}first\r\nsecond\r\n";
+    let mut editor = Editor::new(Buffer::from_text(text), Viewport::new(5, 40));
+
+    // Assert
+    assert!(editor.buffer().has_bom());
+    assert!(editor.buffer().has_final_newline());
+    assert_eq!(editor.buffer().line_count(), 2);
+    assert_eq!(editor.buffer().line(0), Some("first"));
+    assert_eq!(editor.buffer().as_text(), text);
+
+    // Act
+    editor.apply(Action::SelectAll);
+    editor.apply(Action::Copy);
+    editor.set_bom(false);
+
+    // Assert
+    assert_eq!(
+        editor.register('"'),
+        Some(RegisterContent::charwise("first\nsecond"))
+    );
+    assert!(editor.is_dirty());
+    assert_eq!(editor.buffer().as_text(), "first\r\nsecond\r\n");
+}
+
+#[test]
+fn ensure_final_newline_adds_missing_line_break() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("no newline"), Viewport::new(5, 40));
+
+    // Act
+    editor.set_ensure_final_newline(true);
+
+    // Assert
+    assert!(!editor.buffer().has_final_newline());
+    assert!(editor.is_dirty());
+    assert_eq!(editor.buffer().as_text(), "no newline\n");
+
+    // Act
+    editor.reset_dirty();
+    editor.set_final_newline(true);
+    let mut empty = Buffer::new();
+    empty.set_ensure_final_newline(true);
+
+    // Assert
+    assert!(!editor.is_dirty());
+    assert_eq!(editor.buffer().as_text(), "no newline\n");
+    assert_eq!(empty.as_text(), "");
+}
+
+#[test]
+fn legacy_single_byte_encodings_round_trip() {
+    // Init
+    let bytes = b"caf\xe9 \x80 \x93quoted\x94\r\n";
+    let latin1 = Buffer::from_bytes(bytes, Some(Encoding::Latin1)).unwrap();
+    let cp1252 = Buffer::from_bytes(bytes, None).unwrap();
+
+    // Assert
+    assert_eq!(cp1252.encoding(), Encoding::Windows1252);
+    assert_eq!(cp1252.line(0), Some("café € “quoted”"));
+    assert_eq!(latin1.line(0), Some("café \u{80} \u{93}quoted\u{94}"));
+    assert_eq!(cp1252.to_bytes(Encoding::Windows1252).unwrap(), bytes);
+    assert_eq!(latin1.to_bytes(Encoding::Latin1).unwrap(), bytes);
+    assert_eq!(
+        cp1252.to_bytes(Encoding::Latin1),
+        Err(EncodingError::Unrepresentable(vec![
+            (Cursor::new(0, 5), '€'),
+            (Cursor::new(0, 7), '“'),
+            (Cursor::new(0, 14), '”'),
+        ]))
+    );
+}
+
+#[test]
+fn utf16_is_detected_and_re_encoded() {
+    // Init
+    let text = "h\u{e9}llo\n\u{1f600}";
+    let mut with_bom = vec![0xff, 0xfe];
+    let mut big_endian = Vec::new();
+    for unit in text.encode_utf16() {
+        with_bom.extend_from_slice(&unit.to_le_bytes());
+        big_endian.extend_from_slice(&unit.to_be_bytes());
+    }
+
+    // Act
+    let little = Buffer::from_bytes(&with_bom, None).unwrap();
+    let big = Buffer::from_bytes(&big_endian, None).unwrap();
+
+    // Assert
+    assert_eq!(little.encoding(), Encoding::Utf16Le);
+    assert!(little.has_bom());
+    assert_eq!(little.line(0), Some("héllo"));
+    assert_eq!(little.to_bytes(Encoding::Utf16Le).unwrap(), with_bom);
+    assert_eq!(big.encoding(), Encoding::Utf16Be);
+    assert!(!big.has_bom());
+    assert_eq!(big.as_text(), text);
+    assert_eq!(big.to_bytes(Encoding::Utf16Be).unwrap(), big_endian);
+    assert_eq!(big.to_bytes(Encoding::Utf8).unwrap(), text.as_bytes());
+    assert_eq!(
+        Buffer::from_bytes(&big_endian[..3], Some(Encoding::Utf16Be)),
+        Err(EncodingError::Invalid { offset: 2 })
+    );
+}
+
+#[test]
+fn invalid_utf8_bytes_round_trip_as_escape_units() {
+    // Init
+    let mut bytes = b"ok\xff\xfe caf\xc3\xa9 \xe2\x82!\n".to_vec();
+    bytes.extend_from_slice("\u{10ff85}".as_bytes());
+    let buffer = Buffer::from_bytes(&bytes, Some(Encoding::Utf8Lossless)).unwrap();
+    let mut editor = Editor::new(buffer, Viewport::new(5, 40));
+
+    // Assert
+    assert_eq!(editor.buffer().line_len_chars(0), 13);
+    assert_eq!(editor.buffer().line_len_chars(1), 4);
+    assert_eq!(
+        editor.visible_lines(),
+        vec![r"ok\xFF\xFE café \xE2\x82!", r"\xF4\x8F\xBE\x85"]
+    );
+    assert_eq!(
+        editor.buffer().to_bytes(Encoding::Utf8Lossless).unwrap(),
+        bytes
+    );
+    assert_eq!(
+        Buffer::from_bytes(&bytes, Some(Encoding::Utf8)),
+        Err(EncodingError::Invalid { offset: 2 })
+    );
+
+    // Act
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::MoveRight);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 3));
+    assert_eq!(editor.cursor_screen_pos(), (0, 6));
+
+    // Act
+    editor.apply(Action::DeleteBackward);
+    editor.apply(Action::Insert('-'));
+
+    // Assert
+    assert_eq!(
+        editor.buffer().to_bytes(Encoding::Utf8Lossless).unwrap()[..5],
+        *b"ok-\xfe "
+    );
+}
+
+#[test]
+fn escape_units_scroll_by_display_columns() {
+    // Init
+    let buffer = Buffer::from_bytes(b"ab\x80\x81cd", Some(Encoding::Utf8Lossless)).unwrap();
+    let mut editor = Editor::new(buffer, Viewport::new(1, 5));
+
+    // Act
+    for _ in 0..4 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 4));
+    assert_eq!(editor.viewport().col_offset, 6);
+    assert_eq!(editor.cursor_screen_pos(), (0, 4));
+    assert_eq!(editor.visible_lines(), vec![r"\x81c"]);
+}
+
+#[test]
+fn control_chars_take_no_cells_and_are_not_drawn() {
+    // Init
+    let buffer = Buffer::from_text("ab\u{1b}[31mred\u{7}\u{85}\u{7f}!");
+    let mut editor = Editor::new(buffer, Viewport::new(1, 20));
+
+    // Act
+    editor.apply(Action::MoveLineEnd);
+
+    // Assert
+    assert_eq!(editor.buffer().plain_prefix_chars(0), 2);
+    assert_eq!(editor.cursor(), Cursor::new(0, 14));
+    assert_eq!(editor.cursor_screen_pos(), (0, 10));
+    assert_eq!(editor.visible_lines(), vec!["ab[31mred!"]);
+}
+
+#[test]
+fn cursor_moves_and_deletes_by_grapheme_cluster() {
+    // Init
+    let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+    let text =
+        format!("e\u{301}{family}\u{1f1eb}\u{1f1f7}\u{1f1e9}\u{1f1ea}\u{1100}\u{1161}\u{11a8}!");
+    let mut editor = Editor::new(Buffer::from_text(&text), Viewport::new(5, 80));
+
+    // Act
+    let mut cols = Vec::new();
+    for _ in 0..6 {
+        editor.apply(Action::MoveRight);
+        cols.push(editor.cursor().col);
+    }
+
+    // Assert
+    assert_eq!(cols, vec![2, 7, 9, 11, 14, 15]);
+
+    // Act
+    editor.apply(Action::MoveLeft);
+    editor.apply(Action::MoveLeft);
+    editor.apply(Action::DeleteBackward);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 9));
+    assert_eq!(
+        editor.buffer().line(0).unwrap(),
+        format!("e\u{301}{family}\u{1f1eb}\u{1f1f7}\u{1100}\u{1161}\u{11a8}!")
+    );
+
+    // Act
+    editor.apply(Action::MoveLeft);
+    editor.apply(Action::MoveLeft);
+    editor.apply(Action::DeleteForward);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 2));
+    assert_eq!(
+        editor.buffer().line(0).unwrap(),
+        "e\u{301}\u{1f1eb}\u{1f1f7}\u{1100}\u{1161}\u{11a8}!"
+    );
+}
+
+#[test]
+fn char_level_movement_can_be_restored() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("e\u{301}x"), Viewport::new(5, 40));
+    assert!(editor.grapheme_clusters());
+
+    // Act
+    editor.set_grapheme_clusters(false);
+    editor.apply(Action::MoveRight);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 1));
+
+    // Act
+    editor.apply(Action::DeleteForward);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "ex");
+}
+
+#[test]
+fn wide_characters_use_two_display_columns() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("ab日本語e\u{301}x"), Viewport::new(1, 5));
+
+    // Assert
+    assert_eq!(editor.visible_lines(), vec!["ab日 "]);
+
+    // Act
+    for _ in 0..3 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 3));
+    assert_eq!(editor.viewport().col_offset, 1);
+    assert_eq!(editor.cursor_screen_pos(), (0, 3));
+    assert_eq!(editor.visible_lines(), vec!["b日本"]);
+
+    // Act
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::MoveRight);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 5));
+    assert_eq!(editor.viewport().col_offset, 4);
+    assert_eq!(editor.cursor_screen_pos(), (0, 4));
+    assert_eq!(editor.visible_lines(), vec!["本語e\u{301}"]);
+}
+
+#[test]
+fn visible_lines_pad_wide_characters_cut_by_the_edges() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("a漢字b"), Viewport::new(1, 4));
+
+    // Assert
+    assert_eq!(editor.visible_lines(), vec!["a漢 "]);
+
+    // Act
+    for _ in 0..3 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Assert
+    assert_eq!(editor.viewport().col_offset, 2);
+    assert_eq!(editor.visible_lines(), vec![" 字b"]);
+}
+
+#[test]
+fn tabs_expand_to_the_next_tab_stop() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("\tab\tc\n  \tx"), Viewport::new(2, 12));
+
+    // Assert
+    assert_eq!(editor.tab_width(), 4);
+    assert_eq!(editor.visible_lines(), vec!["    ab  c", "    x"]);
+
+    // Act
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::MoveRight);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 3));
+    assert_eq!(editor.cursor_screen_pos(), (0, 6));
+
+    // Act
+    editor.set_tab_width(8);
+
+    // Assert
+    assert_eq!(editor.cursor_screen_pos(), (0, 10));
+    assert_eq!(editor.visible_lines(), vec!["        ab  ", "        x"]);
+}
+
+#[test]
+fn horizontal_scroll_keeps_tabs_in_display_columns() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("a\tb\tc"), Viewport::new(1, 3));
+
+    // Act
+    editor.apply(Action::MoveRight);
+
+    // Assert
+    assert_eq!(editor.viewport().col_offset, 0);
+    assert_eq!(editor.cursor_screen_pos(), (0, 1));
+    assert_eq!(editor.visible_lines(), vec!["a  "]);
+
+    // Act
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::MoveRight);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 3));
+    assert_eq!(editor.viewport().col_offset, 3);
+    assert_eq!(editor.cursor_screen_pos(), (0, 2));
+    assert_eq!(editor.visible_lines(), vec![" b "]);
+}
+
+#[test]
+fn soft_wrap_splits_long_lines_into_visual_rows() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("abcdefghij\nxy"), Viewport::new(2, 4));
+    editor.set_soft_wrap(true);
+
+    // Assert
+    assert_eq!(editor.visible_lines(), vec!["abcd", "efgh"]);
+    assert_eq!(
+        editor.visual_rows(),
+        vec![
+            VisualRow {
+                row: 0,
+                start_col: 0,
+                end_col: 4
+            },
+            VisualRow {
+                row: 0,
+                start_col: 4,
+                end_col: 8
+            },
+        ]
+    );
+
+    // Act
+    for _ in 0..9 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Assert
+    assert_eq!(editor.viewport().row_offset, 0);
+    assert_eq!(editor.viewport().wrap_offset, 1);
+    assert_eq!(editor.cursor_screen_pos(), (1, 1));
+    assert_eq!(editor.visible_lines(), vec!["efgh", "ij"]);
+
+    // Act
+    editor.apply(Action::MoveDown);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(1, 2));
+    assert_eq!(editor.viewport().wrap_offset, 2);
+    assert_eq!(editor.cursor_screen_pos(), (1, 2));
+    assert_eq!(editor.visible_lines(), vec!["ij", "xy"]);
+}
+
+#[test]
+fn soft_wrap_keeps_the_cursor_of_a_full_last_row_on_screen() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("abcdefgh\nxy"), Viewport::new(2, 4));
+    editor.set_soft_wrap(true);
+
+    // Act
+    editor.apply(Action::MoveLineEnd);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 8));
+    assert_eq!(editor.viewport().wrap_offset, 0);
+    assert_eq!(editor.cursor_screen_pos(), (1, 3));
+    assert_eq!(editor.visible_lines(), vec!["abcd", "efgh"]);
+
+    // Act
+    editor.apply(Action::Insert('i'));
+
+    // Assert
+    assert_eq!(editor.cursor_screen_pos(), (1, 1));
+    assert_eq!(editor.visible_lines(), vec!["efgh", "i"]);
+}
+
+#[test]
+fn soft_wrap_can_break_at_words_and_indent_continuations() {
+    // Init
+    let mut editor = Editor::new(
+        Buffer::from_text("the quick brown fox"),
+        Viewport::new(3, 10),
+    );
+
+    // Assert
+    assert_eq!(
+        editor.visual_rows(),
+        vec![VisualRow {
+            row: 0,
+            start_col: 0,
+            end_col: 10
+        }]
+    );
+
+    // Act
+    editor.set_soft_wrap(true);
+
+    // Assert
+    assert_eq!(editor.visible_lines(), vec!["the quick ", "brown fox"]);
+
+    // Act
+    editor.set_wrap_at_words(true);
+    editor.set_wrap_indent(2);
+    for _ in 0..19 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Assert
+    assert_eq!(
+        editor.visible_lines(),
+        vec!["the quick ", "  brown ", "  fox"]
+    );
+    assert_eq!(editor.visual_rows()[1].start_col, 10);
+    assert_eq!(editor.visual_rows()[2].start_col, 16);
+    assert_eq!(editor.cursor_screen_pos(), (2, 5));
+}
+
+#[test]
+fn visual_moves_step_through_wrapped_rows_keeping_the_screen_column() {
+    // Init
+    let mut editor = Editor::new(
+        Buffer::from_text("abcdefghij\nx\nabcdef"),
+        Viewport::new(5, 4),
+    );
+    editor.set_soft_wrap(true);
+    editor.apply(Action::MoveRight);
+    editor.apply(Action::MoveRight);
+
+    // Act
+    let mut visited = Vec::new();
+    for _ in 0..4 {
+        editor.apply(Action::MoveVisualDown);
+        visited.push(editor.cursor());
+    }
+
+    // Assert
+    assert_eq!(
+        visited,
+        vec![
+            Cursor::new(0, 6),
+            Cursor::new(0, 10),
+            Cursor::new(1, 1),
+            Cursor::new(2, 2),
+        ]
+    );
+
+    // Act
+    editor.apply(Action::MoveVisualUp);
+    editor.apply(Action::MoveVisualUp);
+    editor.apply(Action::MoveVisualUp);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 6));
+    assert_eq!(editor.cursor_screen_pos(), (1, 2));
+
+    // Act
+    editor.apply(Action::MoveDown);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(1, 1));
+}
+
+#[test]
+fn visual_selects_extend_the_selection_by_wrapped_rows() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("abcdefghij\nxy"), Viewport::new(3, 4));
+    editor.set_soft_wrap(true);
+    for _ in 0..6 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Act
+    editor.apply(Action::SelectVisualLineStart);
+
+    // Assert
+    assert_eq!(
+        editor.selection(),
+        Selection::new(Cursor::new(0, 6), Cursor::new(0, 4))
+    );
+
+    // Act
+    editor.apply(Action::SelectVisualLineEnd);
+    editor.apply(Action::SelectVisualDown);
+
+    // Assert
+    assert_eq!(
+        editor.selection(),
+        Selection::new(Cursor::new(0, 6), Cursor::new(0, 10))
+    );
+
+    // Act
+    editor.apply(Action::SelectVisualDown);
+    editor.apply(Action::SelectVisualUp);
+
+    // Assert
+    assert_eq!(
+        editor.selection(),
+        Selection::new(Cursor::new(0, 6), Cursor::new(0, 10))
+    );
+    assert_eq!(editor.selected_text(), "ghij");
+}
+
+#[test]
+fn visual_line_start_and_end_stay_on_the_wrapped_row() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("abcdefghij"), Viewport::new(3, 4));
+    editor.set_soft_wrap(true);
+    for _ in 0..6 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Act
+    editor.apply(Action::MoveVisualLineStart);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 4));
+
+    // Act
+    editor.apply(Action::MoveVisualLineEnd);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 7));
+    assert_eq!(editor.cursor_screen_pos(), (1, 3));
+
+    // Act
+    editor.apply(Action::MoveVisualDown);
+    editor.apply(Action::MoveVisualLineEnd);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 10));
+
+    // Act
+    editor.set_soft_wrap(false);
+    editor.apply(Action::MoveVisualLineStart);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 0));
+}
+
+#[test]
+fn vertical_moves_remember_the_preferred_column() {
+    // Init
+    let mut editor = Editor::new(
+        Buffer::from_text("abcdef\n\nabcdef\nab"),
+        Viewport::new(4, 10),
+    );
+    for _ in 0..4 {
+        editor.apply(Action::MoveRight);
+    }
+
+    // Act
+    let mut visited = Vec::new();
+    for action in [
+        Action::MoveDown,
+        Action::MoveDown,
+        Action::MoveDown,
+        Action::MoveUp,
+    ] {
+        editor.apply(action);
+        visited.push(editor.cursor());
+    }
+
+    // Assert
+    assert_eq!(
+        visited,
+        vec![
+            Cursor::new(1, 0),
+            Cursor::new(2, 4),
+            Cursor::new(3, 2),
+            Cursor::new(2, 4),
+        ]
+    );
+
+    // Act
+    editor.apply(Action::MoveLeft);
+    editor.apply(Action::MoveUp);
+    editor.apply(Action::MoveUp);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 3));
+
+    // Act
+    editor.apply(Action::MoveDown);
+    editor.apply(Action::Insert('x'));
+    editor.apply(Action::MoveDown);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(2, 1));
+}
+
+#[test]
+fn preferred_column_is_measured_in_display_columns() {
+    // Init
+    let mut editor = Editor::new(
+        Buffer::from_text("\tx\nabcdefgh\n日本語"),
+        Viewport::new(3, 10),
+    );
+    editor.apply(Action::MoveRight);
+
+    // Act
+    editor.apply(Action::SelectDown);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(1, 4));
+
+    // Act
+    editor.apply(Action::SelectDown);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(2, 2));
+    assert_eq!(editor.cursor_screen_pos(), (2, 4));
+
+    // Act
+    editor.apply(Action::MoveUp);
+    editor.apply(Action::MoveUp);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(0, 1));
+    assert_eq!(editor.selection(), Selection::caret(Cursor::new(0, 1)));
+}
+
+#[test]
+fn vertical_moves_land_on_grapheme_cluster_starts() {
+    // Init
+    let mut editor = Editor::new(
+        Buffer::from_text("abcdefgh\n👨\u{200d}👩\u{200d}👧x"),
+        Viewport::new(2, 20),
+    );
+    editor.set_selection(Selection::caret(Cursor::new(0, 3)));
+
+    // Act
+    editor.apply(Action::MoveDown);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(1, 0));
+
+    // Act
+    editor.apply(Action::MoveUp);
+    editor.apply(Action::MoveVisualDown);
+    editor.apply(Action::DeleteForward);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "abcdefgh\nx");
+
+    // Act
+    editor.set_grapheme_clusters(false);
+    editor.apply(Action::Undo);
+    editor.set_selection(Selection::caret(Cursor::new(0, 3)));
+    editor.apply(Action::MoveDown);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(1, 2));
+}
+
+#[test]
+fn line_and_buffer_boundary_motions() {
+    // Init
+    let mut editor = Editor::new(
+        Buffer::from_text("    fn main()\nx\n  end"),
+        Viewport::new(3, 20),
+    );
+    editor.set_selection(Selection::caret(Cursor::new(0, 6)));
+
+    // Act
+    let mut visited = Vec::new();
+    for action in [
+        Action::MoveLineStart,
+        Action::MoveFirstNonWhitespace,
+        Action::MoveFirstNonWhitespace,
+        Action::MoveLineEnd,
+        Action::MoveBufferEnd,
+        Action::MoveBufferStart,
+    ] {
+        editor.apply(action);
+        visited.push(editor.cursor());
+    }
+
+    // Assert
+    assert_eq!(
+        visited,
+        vec![
+            Cursor::new(0, 0),
+            Cursor::new(0, 4),
+            Cursor::new(0, 0),
+            Cursor::new(0, 13),
+            Cursor::new(2, 5),
+            Cursor::new(0, 0),
+        ]
+    );
+
+    // Act
+    editor.apply(Action::SelectFirstNonWhitespace);
+    editor.apply(Action::SelectLineEnd);
+
+    // Assert
+    assert_eq!(
+        editor.selection(),
+        Selection::new(Cursor::new(0, 0), Cursor::new(0, 13))
+    );
+    assert_eq!(editor.selected_text(), "    fn main()");
+}
+
+#[test]
+fn goto_line_and_position_clamp_to_the_buffer() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("abc\nx\n  end"), Viewport::new(3, 20));
+
+    // Act
+    editor.apply(Action::GotoLine(2));
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(2, 0));
+
+    // Act
+    editor.apply(Action::GotoLine(99));
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(2, 0));
+
+    // Act
+    editor.apply(Action::GotoPosition(Cursor::new(1, 50)));
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(1, 1));
+
+    // Act
+    editor.apply(Action::SelectBufferEnd);
+
+    // Assert
+    assert_eq!(editor.selected_text(), "\n  end");
+
+    // Act
+    editor.apply(Action::GotoPosition(Cursor::new(9, 9)));
+
+    // Assert
+    assert_eq!(editor.selection(), Selection::caret(Cursor::new(2, 5)));
+}
+
+#[test]
+fn page_moves_scroll_the_viewport_with_the_cursor() {
+    // Init
+    let text: Vec<String> = (0..20).map(|row| format!("line{row}")).collect();
+    let mut editor = Editor::new(Buffer::from_text(&text.join("\n")), Viewport::new(5, 10));
+    editor.apply(Action::GotoLine(2));
+
+    // Act
+    let mut visited = Vec::new();
+    for action in [
+        Action::PageDown,
+        Action::PageDown,
+        Action::PageDown,
+        Action::PageDown,
+        Action::PageUp,
+        Action::HalfPageUp,
+    ] {
+        editor.apply(action);
+        visited.push((
+            editor.viewport().row_offset,
+            editor.cursor().row,
+            editor.cursor_screen_pos().0,
+        ));
+    }
+
+    // Assert
+    assert_eq!(
+        visited,
+        vec![
+            (5, 7, 2),
+            (10, 12, 2),
+            (15, 17, 2),
+            (15, 19, 4),
+            (10, 14, 4),
+            (8, 12, 4),
+        ]
+    );
+}
+
+#[test]
+fn half_page_moves_count_wrapped_rows_and_keep_the_column() {
+    // Init
+    let mut editor = Editor::new(
+        Buffer::from_text("abcdefgh\nab\nabcdefgh\nabcd"),
+        Viewport::new(4, 4),
+    );
+    editor.set_soft_wrap(true);
+    for _ in 0..3 {
+        editor.apply(Action::SelectRight);
+    }
+
+    // Act
+    editor.apply(Action::HalfPageDown);
+
+    // Assert
+    assert_eq!(editor.selection(), Selection::caret(Cursor::new(1, 2)));
+    assert_eq!(editor.viewport().row_offset, 1);
+    assert_eq!(editor.cursor_screen_pos(), (0, 2));
+
+    // Act
+    editor.apply(Action::HalfPageDown);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(2, 7));
+    assert_eq!(editor.viewport().row_offset, 1);
+    assert_eq!(editor.cursor_screen_pos(), (2, 3));
+
+    // Act
+    editor.apply(Action::HalfPageUp);
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(1, 2));
+    assert_eq!(
+        (editor.viewport().row_offset, editor.viewport().wrap_offset),
+        (0, 0)
+    );
+    assert_eq!(editor.cursor_screen_pos(), (2, 2));
+}
+
+#[test]
+fn cursor_display_column_skips_the_plain_prefix() {
+    // Init
+    let line = "a".repeat(1_000);
+    let mut editor = Editor::new(Buffer::from_text(&line), Viewport::new(5, 80));
+    editor.apply(Action::MoveLineEnd);
+
+    // Act
+    for _ in 0..100 {
+        editor.apply(Action::Insert('x'));
+    }
+    for _ in 0..100 {
+        editor.apply(Action::MoveLeft);
+    }
+
+    // Assert
+    assert_eq!(editor.buffer().plain_prefix_chars(0), 1_100);
+    assert_eq!(editor.cursor(), Cursor::new(0, 1_000));
+    assert_eq!(editor.viewport().col_offset, 1_000);
+    assert_eq!(editor.cursor_screen_pos(), (0, 0));
+
+    // Act
+    editor.apply(Action::Insert('\t'));
+
+    // Assert
+    assert_eq!(editor.buffer().plain_prefix_chars(0), 1_000);
+    assert_eq!(editor.cursor(), Cursor::new(0, 1_001));
+    assert_eq!(editor.cursor_screen_pos(), (0, 4));
+
+    // Act
+    editor.apply(Action::MoveBufferStart);
+    editor.apply(Action::Insert('\t'));
+
+    // Assert
+    assert_eq!(editor.buffer().plain_prefix_chars(0), 0);
+    assert_eq!(editor.cursor_screen_pos(), (0, 4));
+}
+
+#[test]
+fn cut_of_zero_width_block_removes_nothing() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("abcd\nefgh"), Viewport::new(2, 10));
+    editor.set_selection(Selection::caret(Cursor::new(0, 2)));
+
+    // Act
+    editor.apply(Action::BlockSelectDown);
+    editor.apply(Action::Cut);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "abcd\nefgh");
+    assert!(!editor.is_dirty());
+    assert_eq!(
+        editor.register('"'),
+        Some(RegisterContent::new("\n", RegisterKind::Blockwise))
+    );
+}
+
+#[test]
+fn pasted_blocks_keep_text_to_their_right_aligned() {
+    // Init
+    let mut editor = Editor::new(
+        Buffer::from_text("abc\na\nXXXX\nYYYY"),
+        Viewport::new(4, 10),
+    );
+    editor.set_selection(Selection::caret(Cursor::new(0, 1)));
+    editor.apply(Action::BlockSelectDown);
+    editor.apply(Action::BlockSelectRight);
+    editor.apply(Action::BlockSelectRight);
+    let rows = editor.block_text().unwrap();
+
+    // Act
+    editor.set_selection(Selection::caret(Cursor::new(2, 1)));
+    editor.paste_block(&rows);
+
+    // Assert
+    assert_eq!(rows, vec!["bc", ""]);
+    assert_eq!(editor.buffer().as_text(), "abc\na\nXbcXXX\nY  YYY");
+
+    // Act
+    editor.apply(Action::Undo);
+    editor.set_selection(Selection::caret(Cursor::new(0, 1)));
+    editor.apply(Action::BlockSelectDown);
+    editor.apply(Action::BlockSelectRight);
+    editor.apply(Action::BlockSelectRight);
+    editor.apply(Action::Copy);
+    editor.set_selection(Selection::caret(Cursor::new(2, 4)));
+    editor.apply(Action::Paste);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "abc\na\nXXXXbc\nYYYY");
+
+    // Act
+    editor.apply(Action::Undo);
+    editor.set_selection(Selection::caret(Cursor::new(2, 1)));
+    editor.apply(Action::Paste);
+
+    // Assert
+    assert_eq!(editor.buffer().as_text(), "abc\na\nXbcXXX\nY  YYY");
+}
+
+#[test]
+fn inserting_carriage_return_splits_the_line() {
+    // Init
+    let mut editor = Editor::new(Buffer::from_text("ab\r\ncd"), Viewport::new(3, 10));
+    editor.apply(Action::MoveRight);
+
+    // Act
+    editor.apply(Action::Insert('\r'));
+
+    // Assert
+    assert_eq!(editor.cursor(), Cursor::new(1, 0));
+    assert_eq!(editor.buffer().line_count(), 3);
+    assert_eq!(editor.buffer().as_text(), "a\r\nb\r\ncd");
+    assert_eq!(
+        Buffer::from_text(&editor.buffer().as_text()).line_count(),
+        3
+    );
+}
+
+#[test]
+fn edits_inside_chunked_long_lines_match_a_plain_string() {
+    // Init
+    let line = "{\"k\": [1, 2], \"v\": \"é日\"}".repeat(1_000);
+    let mut model: Vec<char> = line.chars().collect();
+    let mut buffer = Buffer::from_text(&line);
+    let mut seed = 7_u64;
+    let mut next = |bound: usize| {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+        (seed >> 33) as usize % bound.max(1)
+    };
+
+    // Act
+    for step in 0..300 {
+        let col = next(model.len() + 1);
+        if step % 3 == 0 {
+            let end = (col + next(400)).min(model.len());
+            let removed = buffer.remove(Cursor::new(0, col), Cursor::new(0, end));
+            let expected: String = model.drain(col..end).collect();
+            assert_eq!(removed, expected);
+        } else {
+            let text = ["x", "ü\t", "😀", "abc日本"][step % 4].repeat(next(200) + 1);
+            buffer.insert(Cursor::new(0, col), &text);
+            model.splice(col..col, text.chars());
+        }
+        if step % 50 == 0 {
+            let at = next(model.len() + 1);
+            assert_eq!(buffer.insert(Cursor::new(0, at), "\n"), Cursor::new(1, 0));
+            assert_eq!(buffer.line_len_chars(0), at);
+            assert_line_lookups_match(&buffer, 1);
+            buffer.remove(Cursor::new(0, at), Cursor::new(1, 0));
+        }
+    }
+
+    // Assert
+    let expected: String = model.iter().collect();
+    assert!(expected.len() > 20_000);
+    assert_eq!(buffer.line_count(), 1);
+    assert_eq!(buffer.line(0).unwrap(), expected);
+    assert_eq!(buffer.text(), expected);
+    assert_eq!(
+        buffer.text_range(Cursor::new(0, 1_000), Cursor::new(0, 9_000)),
+        model[1_000..9_000].iter().collect::<String>()
+    );
+    assert_line_lookups_match(&buffer, 0);
+}
+
+#[test]
+fn grapheme_moves_inside_long_runs_of_flags() {
+    // Init
+    let flags = "\u{1f1eb}\u{1f1f7}".repeat(3_000);
+    let mut editor = Editor::new(Buffer::from_text(&flags), Viewport::new(5, 80));
+    editor.set_selection(Selection::caret(Cursor::new(0, 3_000)));
+
+    // Act
+    editor.apply(Action::MoveRight);
+    let right = editor.cursor();
+    editor.apply(Action::MoveLeft);
+    editor.apply(Action::MoveLeft);
+    let left = editor.cursor();
+    editor.apply(Action::DeleteBackward);
+
+    // Assert
+    assert_eq!(right, Cursor::new(0, 3_002));
+    assert_eq!(left, Cursor::new(0, 2_998));
+    assert_eq!(editor.cursor(), Cursor::new(0, 2_996));
+    assert_eq!(editor.buffer().line_len_chars(0), 5_998);
+    assert_eq!(
+        editor.buffer().line(0).unwrap(),
+        "\u{1f1eb}\u{1f1f7}".repeat(2_999)
+    );
+}